@@ -9,6 +9,44 @@ use dsp_lab::emulation::Hysteresis;
 use dsp_lab::utils::math::x_fade;
 
 
+// dropout LFO's
+// They are mutually irrational, so they never sync up
+// - drop_1 is  7 * e/2.7
+// - drop_2 is 11 * e/2.7 * e/2.7
+const DROP_FREQ_1: f64 = 7.04739733304196912871185640721060647566693690959;
+const DROP_FREQ_2: f64 = 11.1494673646415847050116189391392436138523280214;
+
+// === SEEDS ===
+// NOTE: every random source and LFO start phase is derived from one base seed,
+// each source with its own stream number. Base seed 0 gives every source the
+// fixed seed it had before the seed could be chosen (the stream number itself)
+// and starts all LFO's at phase 0, so that old sessions sound the same.
+
+// splitmix64, turns a counter into well spread bits
+fn mix(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9E3779B97F4A7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+    z ^ (z >> 31)
+}
+
+/// Seed of one random source.
+pub fn derive_seed(base: u64, stream: u64) -> u64 {
+    if base == 0 {
+        return stream;
+    }
+    mix(base ^ mix(stream))
+}
+
+/// Start phase of one LFO, between 0 and 1.
+pub fn derive_phase(base: u64, stream: u64) -> f64 {
+    if base == 0 {
+        return 0.0;
+    }
+    (derive_seed(base, stream) >> 11) as f64 / (1u64 << 53) as f64
+}
+
+
 pub struct Dropouts {
     lfo_1: SnhRandom,
     lfo_2: SnhRandom,
@@ -20,20 +58,293 @@ impl Dropouts {
             lfo_1: SnhRandom::new(44100.0, 10),
             lfo_2: SnhRandom::new(44100.0, 11),
         };
-        ret.lfo_1.set_freq(7.04739733304196912871185640721060647566693690959);
-        ret.lfo_2.set_freq(11.1494673646415847050116189391392436138523280214);
+        ret.rebuild(44100.0, 0);
         return ret;
     }
 
-    pub fn set_sr(&mut self, sr: f64) {
-        // TODO: make this a thing
-        // parent.lfo_1.sr = sr;
-        // parent.lfo_2.sr = sr;
+    // SnhRandom can't change its sample rate or seed after being instantiated,
+    // so the LFO's are rebuilt, this doesn't allocate. All the dropouts of an
+    // instance share their seeds, so that they hit all the lines at once, like
+    // a crease on a real tape.
+    pub fn rebuild(&mut self, sr: f64, seed: u64) {
+        self.lfo_1 = SnhRandom::new(sr, derive_seed(seed, 10));
+        self.lfo_2 = SnhRandom::new(sr, derive_seed(seed, 11));
+        self.lfo_1.set_freq(DROP_FREQ_1);
+        self.lfo_2.set_freq(DROP_FREQ_2);
+    }
+
+    // the gain envelope on its own, so that it can be applied to more than one
+    // signal
+    pub fn next_gain(&mut self) -> f64 {
+        ((self.lfo_1.step() + self.lfo_2.step()) * 0.5).abs().sqrt().sqrt() /*.sqrt()*/
     }
 }
 
 impl Process<f64> for Dropouts {
     fn step(&mut self, input: f64) -> f64 {
-        ((self.lfo_1.step() + self.lfo_2.step()) * 0.5).abs().sqrt().sqrt() /*.sqrt()*/ * input
+        self.next_gain() * input
+    }
+}
+
+// A tape loop with any number of read heads. Unlike a delay line, the heads are
+// read separately, so each one can go to a different place. Offsets are in ms.
+pub struct Tape {
+    buf: Vec<f64>,
+    pos: usize,     // index of the last written sample
+    sr: f64,
+}
+
+impl Tape {
+    // allocates, never call this from the audio thread
+    pub fn new(max_len: f64, sr: f64) -> Self {
+        Self {
+            buf: vec![0.0; (max_len * 0.001 * sr).ceil() as usize + 4],
+            pos: 0,
+            sr: sr,
+        }
+    }
+
+    pub fn write(&mut self, input: f64) {
+        self.pos = (self.pos + 1) % self.buf.len();
+        self.buf[self.pos] = input;
+    }
+
+    // quadratic interpolation between the three samples around the head
+    pub fn read(&self, offset: f64) -> f64 {
+        let len = self.buf.len();
+        let delay = (offset * 0.001 * self.sr).max(2.0).min((len - 3) as f64);
+        let idx = self.pos as f64 - delay + len as f64;
+        let frac = idx - idx.floor();
+        let i = idx.floor() as usize;
+        let x0 = self.buf[(i + len - 1) % len];
+        let x1 = self.buf[i % len];
+        let x2 = self.buf[(i + 1) % len];
+        x1 + 0.5 * frac * (x2 - x0) + 0.5 * frac * frac * (x2 - 2.0 * x1 + x0)
+    }
+
+    // exactly `samples` behind the last written sample, without interpolation,
+    // so a loop that goes through it over and over doesn't lose any highs
+    pub fn read_exact(&self, samples: usize) -> f64 {
+        let len = self.buf.len();
+        self.buf[(self.pos + len - samples.min(len - 1)) % len]
+    }
+
+    // length in samples, no head can read further back than this
+    pub fn len(&self) -> usize {
+        self.buf.len()
+    }
+
+    // erase the tape, doesn't allocate
+    pub fn clear(&mut self) {
+        for s in self.buf.iter_mut() {
+            *s = 0.0;
+        }
+    }
+}
+
+// Peak envelope follower, the attack and release times are in ms and can
+// change on every sample.
+pub struct Envelope {
+    env: f64,
+    sr: f64,
+}
+
+impl Envelope {
+    pub fn new(sr: f64) -> Self {
+        Self {
+            env: 0.0,
+            sr: sr,
+        }
+    }
+
+    pub fn step(&mut self, input: f64, attack: f64, release: f64) -> f64 {
+        let input = input.abs();
+        let time = if input > self.env {attack} else {release};
+        let coeff = (-1000.0 / (time * self.sr)).exp();
+        self.env = input + (self.env - input) * coeff;
+        return self.env;
+    }
+}
+
+
+// Algorithm version, kept so that old sessions still sound the same.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Engine {
+    // two delay lines with two heads each, the center time is read from both
+    Classic,
+    // a center delay line plus hard panned left and right delay lines
+    TriLine,
+}
+
+impl Engine {
+    pub fn from_param(val: f32) -> Self {
+        if val < 0.5 {Engine::Classic} else {Engine::TriLine}
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Engine::Classic => "classic",
+            Engine::TriLine => "3 lines",
+        }
+    }
+}
+
+
+// What the left and right tape chains are fed with.
+#[derive(Clone, Copy, PartialEq)]
+pub enum StereoMode {
+    LeftRight,
+    // the left chain runs on the mid signal and the right one on the side
+    MidSide,
+}
+
+impl StereoMode {
+    pub fn from_param(val: f32) -> Self {
+        if val < 0.5 {StereoMode::LeftRight} else {StereoMode::MidSide}
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            StereoMode::LeftRight => "L/R",
+            StereoMode::MidSide   => "M/S",
+        }
+    }
+}
+
+
+// What the ducking listens to.
+#[derive(Clone, Copy, PartialEq)]
+pub enum DuckSource {
+    Input,
+    // the second stereo pair of inputs
+    Sidechain,
+}
+
+impl DuckSource {
+    pub fn from_param(val: f32) -> Self {
+        if val < 0.5 {DuckSource::Input} else {DuckSource::Sidechain}
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            DuckSource::Input     => "input",
+            DuckSource::Sidechain => "sidechain",
+        }
+    }
+}
+
+
+// How a pitch shifting read head behaves when it reaches the end of the tape
+// and loops back around.
+#[derive(Clone, Copy, PartialEq)]
+pub enum WrapMode {
+    // jump straight to the other end, the "chopped" sound
+    Hard,
+    // a second head takes over with a short crossfade around the jump
+    Crossfade,
+    // two heads half a tape apart, each faded by a triangular window, like an
+    // overlap-add pitch shifter
+    Overlap,
+}
+
+impl WrapMode {
+    pub fn from_param(val: f32) -> Self {
+        match (val * 2.0).round() as u32 {
+            0 => WrapMode::Hard,
+            1 => WrapMode::Crossfade,
+            _ => WrapMode::Overlap,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            WrapMode::Hard      => "hard",
+            WrapMode::Crossfade => "crossfade",
+            WrapMode::Overlap   => "overlap",
+        }
     }
-}
\ No newline at end of file
+}
+
+// length of the crossfade at the wrap point, in ms
+const WRAP_FADE: f64 = 30.0;
+
+/// Positions (in ms) and gains of the two read heads that play a moving head at
+/// `pos`, which loops over `0..span`. The gains always add up to one.
+pub fn wrap_heads(pos: f64, span: f64, mode: WrapMode) -> ((f64, f64), (f64, f64)) {
+    match mode {
+        WrapMode::Hard => ((pos, 1.0), (pos, 0.0)),
+        WrapMode::Crossfade => {
+            // Near the start of the tape, the second head sits a whole span
+            // further back, which is where the first head is about to jump to
+            // (pitching up) or just jumped from (pitching down).
+            let fade = WRAP_FADE.min(span * 0.5);
+            if pos < fade {
+                let g = pos / fade;
+                ((pos, g), (pos + span, 1.0 - g))
+            } else {
+                ((pos, 1.0), (pos, 0.0))
+            }
+        },
+        WrapMode::Overlap => {
+            let half = span * 0.5;
+            let pos_b = if pos < half { pos + half } else { pos - half };
+            let window = |p: f64| 1.0 - (2.0 * p / span - 1.0).abs();
+            ((pos, window(pos)), (pos_b, window(pos_b)))
+        },
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MODES: [WrapMode; 3] = [WrapMode::Hard, WrapMode::Crossfade, WrapMode::Overlap];
+    const SPAN: f64 = 400.0;
+
+    // what the two heads play back from a tape holding a smooth signal
+    fn play(pos: f64, mode: WrapMode) -> f64 {
+        let tape = |delay: f64| (delay * 0.05).sin();
+        let ((pos_a, gain_a), (pos_b, gain_b)) = wrap_heads(pos, SPAN, mode);
+        tape(pos_a) * gain_a + tape(pos_b) * gain_b
+    }
+
+    #[test]
+    fn wrap_gains_add_up_to_one() {
+        for mode in MODES.iter() {
+            for i in 0..=4000 {
+                let ((_, gain_a), (_, gain_b)) = wrap_heads(i as f64 * 0.1, SPAN, *mode);
+                assert!((gain_a + gain_b - 1.0).abs() < 1e-12, "{} at {}", mode.name(), i);
+            }
+        }
+    }
+
+    #[test]
+    fn wrap_is_continuous_both_ways() {
+        // the head moves by 0.01 ms per step, pitching up and down, and loops
+        // around the span a few times
+        let step = 0.01;
+        for mode in [WrapMode::Crossfade, WrapMode::Overlap].iter() {
+            for dir in [-1.0, 1.0].iter() {
+                let mut pos = SPAN * 0.5;
+                let mut last = play(pos, *mode);
+                for _ in 0..(3.0 * SPAN / step) as usize {
+                    pos += dir * step;
+                    if pos < 0.0 {pos += SPAN;}
+                    if pos > SPAN {pos -= SPAN;}
+                    let out = play(pos, *mode);
+                    assert!((out - last).abs() < 0.01, "{} jumps at {} going {}", mode.name(), pos, dir);
+                    last = out;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn hard_wrap_reads_at_the_head() {
+        // what a side that isn't shifted gets, resting at the end of its span
+        for pos in [0.0, SPAN * 0.5, SPAN].iter() {
+            assert_eq!(wrap_heads(*pos, SPAN, WrapMode::Hard), ((*pos, 1.0), (*pos, 0.0)));
+        }
+    }
+}
@@ -0,0 +1,13 @@
+// === GLOBALS ===
+// Maximum number of queued parameter changes, the queue is allocated once so
+// that the audio thread never allocates.
+pub const MAX_CHANGES: usize = 1024;
+
+// A parameter change that lands on a specific sample, counted from the start
+// of the next block to be processed.
+#[derive(Clone, Copy)]
+pub struct ParamChange {
+    pub frame: usize,
+    pub index: i32,
+    pub value: f32,
+}
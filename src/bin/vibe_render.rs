@@ -0,0 +1,249 @@
+// Offline renderer, runs a WAV file through VIBE_MACHINE without a host.
+//
+// usage:
+//     vibe_render <input.wav> <output.wav> [options]
+//
+// options:
+//     --<parameter> <value>   set a parameter (normalized 0..1) before rendering,
+//                             e.g. `--time 0.3 --feedback 0.4`
+//     --seed <seed>           "classic" or a seed number from 1 to 999, as
+//                             shown by the plugin (default classic)
+//     --automation <file>     read time-stamped parameter changes from a file
+//     --tail <seconds>        render this much silence after the input ends,
+//                             so that the feedback can ring out (default 5)
+//     --block <samples>       processing block size (default 512)
+//     --channels <1|2>        channels of the output file (default 2), mono
+//                             input files are always fed to a single input
+//
+// The automation file has one change per line, in the form
+// `<seconds> <parameter> <value>`, blank lines and lines starting with `#` are
+// ignored.
+
+// third-party libs
+use vst::host::HostBuffer;
+use vst::plugin::{Plugin, PluginParameters};
+
+// stl stuff
+use std::env;
+use std::fs;
+use std::process;
+use std::sync::Arc;
+
+// internal dependencies
+use VIBE_MACHINE::Effect;
+
+// === GLOBALS ===
+const DEFAULT_TAIL: f64 = 5.0;
+const DEFAULT_BLOCK: usize = 512;
+
+struct Automation {
+    frame: usize,
+    index: i32,
+    value: f32,
+}
+
+struct Options {
+    input: String,
+    output: String,
+    params: Vec<(String, f32)>,
+    automation: Option<String>,
+    seed: String,
+    tail: f64,
+    block: usize,
+    channels: usize,
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let opts = match parse_args(&args) {
+        Ok(opts) => opts,
+        Err(e) => {
+            eprintln!("error: {}\n", e);
+            eprintln!("usage: vibe_render <input.wav> <output.wav> [--<parameter> <value>]... \
+                       [--automation <file>] [--seed <seed>] [--tail <seconds>] [--block <samples>] [--channels <1|2>]");
+            process::exit(2);
+        }
+    };
+
+    if let Err(e) = render(&opts) {
+        eprintln!("error: {}", e);
+        process::exit(1);
+    }
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut positional = Vec::new();
+    let mut opts = Options {
+        input: String::new(),
+        output: String::new(),
+        params: Vec::new(),
+        automation: None,
+        seed: "classic".to_string(),
+        tail: DEFAULT_TAIL,
+        block: DEFAULT_BLOCK,
+        channels: 2,
+    };
+
+    let mut i = 0;
+    while i < args.len() {
+        let arg = &args[i];
+        if arg.starts_with("--") {
+            let val = args.get(i + 1).ok_or(format!("missing value for {}", arg))?;
+            match &arg[2..] {
+                "automation" => opts.automation = Some(val.clone()),
+                "seed" => opts.seed = val.clone(),
+                "tail" => opts.tail = val.parse().map_err(|_| format!("invalid tail length: {}", val))?,
+                "block" => opts.block = val.parse().map_err(|_| format!("invalid block size: {}", val))?,
+                "channels" => opts.channels = val.parse().map_err(|_| format!("invalid channel count: {}", val))?,
+                name => opts.params.push((name.to_string(),
+                    val.parse().map_err(|_| format!("invalid value for {}: {}", name, val))?)),
+            }
+            i += 2;
+        } else {
+            positional.push(arg.clone());
+            i += 1;
+        }
+    }
+
+    if positional.len() != 2 {
+        return Err("expected an input and an output file".to_string());
+    }
+    if opts.block == 0 {
+        return Err("block size must be at least 1".to_string());
+    }
+    if opts.channels != 1 && opts.channels != 2 {
+        return Err("the output can only have 1 or 2 channels".to_string());
+    }
+    opts.output = positional.pop().unwrap();
+    opts.input = positional.pop().unwrap();
+    return Ok(opts);
+}
+
+fn render(opts: &Options) -> Result<(), String> {
+    // === read input ===
+    let mut reader = hound::WavReader::open(&opts.input).map_err(|e| format!("{}: {}", opts.input, e))?;
+    let spec = reader.spec();
+    let channels = spec.channels as usize;
+    let sr = spec.sample_rate as f64;
+    let interleaved: Vec<f32> = match spec.sample_format {
+        hound::SampleFormat::Float => reader.samples::<f32>().collect::<Result<_, _>>(),
+        hound::SampleFormat::Int => {
+            let norm = (1u64 << (spec.bits_per_sample - 1)) as f32;
+            reader.samples::<i32>().map(|s| s.map(|s| s as f32 / norm)).collect::<Result<_, _>>()
+        }
+    }.map_err(|e| format!("{}: {}", opts.input, e))?;
+
+    // mono files are fed to a single input, the plugin spreads it over both
+    // sides. Anything past two channels is ignored.
+    let frames = interleaved.len() / channels;
+    let in_channels = channels.min(2);
+    let tail = (opts.tail.max(0.0) * sr) as usize;
+    let total = frames + tail;
+    let mut in_l = vec![0.0f32; total];
+    let mut in_r = vec![0.0f32; total];
+    for f in 0..frames {
+        in_l[f] = interleaved[f * channels];
+        in_r[f] = interleaved[f * channels + if channels > 1 { 1 } else { 0 }];
+    }
+
+    // === set up plugin ===
+    let mut effect = Effect::default();
+    effect.init();
+    effect.set_sample_rate(sr as f32);
+    effect.set_block_size(opts.block as i64);
+    let params = effect.get_parameter_object();
+    let num_params = effect.get_info().parameters;
+
+    for (name, val) in opts.params.iter() {
+        let index = find_param(&params, num_params, name)?;
+        params.set_parameter(index, *val);
+    }
+    // the seed is given as the plugin shows it, and never on "instance",
+    // which is random, so that renders always use a fixed one
+    let seed = find_param(&params, num_params, "seed")?;
+    if !params.string_to_parameter(seed, opts.seed.clone()) || params.get_parameter(seed) >= 1.0 {
+        return Err(format!("invalid seed: {}, expected \"classic\" or a number from 1 to 999", opts.seed));
+    }
+    let mut automation = match &opts.automation {
+        Some(path) => read_automation(path, &params, num_params, sr)?,
+        None => Vec::new(),
+    };
+    automation.sort_by_key(|a| a.frame);
+
+    // === render ===
+    // automation goes through the plugin's change queue, so that changes land
+    // on the right sample whatever the block size
+    let mut out_l = vec![0.0f32; total];
+    let mut out_r = vec![0.0f32; total];
+    let mut host_buffer: HostBuffer<f32> = HostBuffer::new(in_channels, opts.channels);
+    let mut next_auto = 0;
+    let mut pos = 0;
+    effect.resume();
+    while pos < total {
+        let end = (pos + opts.block).min(total);
+        while next_auto < automation.len() && automation[next_auto].frame < end {
+            let auto = &automation[next_auto];
+            if !effect.queue_parameter_change(auto.frame.saturating_sub(pos), auto.index, auto.value) {
+                return Err("too many automation points in a single block".to_string());
+            }
+            next_auto += 1;
+        }
+
+        let inputs = [&in_l[pos..end], &in_r[pos..end]];
+        let mut outputs = [&mut out_l[pos..end], &mut out_r[pos..end]];
+        let mut buffer = host_buffer.bind(&inputs[..in_channels], &mut outputs[..opts.channels]);
+        effect.process(&mut buffer);
+        pos = end;
+    }
+    effect.suspend();
+
+    // === write output ===
+    let out_spec = hound::WavSpec {
+        channels: opts.channels as u16,
+        sample_rate: spec.sample_rate,
+        bits_per_sample: 32,
+        sample_format: hound::SampleFormat::Float,
+    };
+    let mut writer = hound::WavWriter::create(&opts.output, out_spec).map_err(|e| format!("{}: {}", opts.output, e))?;
+    for (l, r) in out_l.iter().zip(out_r.iter()) {
+        writer.write_sample(*l).map_err(|e| format!("{}: {}", opts.output, e))?;
+        if opts.channels == 2 {
+            writer.write_sample(*r).map_err(|e| format!("{}: {}", opts.output, e))?;
+        }
+    }
+    writer.finalize().map_err(|e| format!("{}: {}", opts.output, e))?;
+
+    return Ok(());
+}
+
+fn find_param(params: &Arc<dyn PluginParameters>, num_params: i32, name: &str) -> Result<i32, String> {
+    (0..num_params)
+        .find(|i| params.get_parameter_name(*i).eq_ignore_ascii_case(name))
+        .ok_or(format!("unknown parameter: {}", name))
+}
+
+fn read_automation(path: &str, params: &Arc<dyn PluginParameters>, num_params: i32, sr: f64) -> Result<Vec<Automation>, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    let mut ret = Vec::new();
+
+    for (n, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() != 3 {
+            return Err(format!("{}:{}: expected `<seconds> <parameter> <value>`", path, n + 1));
+        }
+        let seconds: f64 = fields[0].parse().map_err(|_| format!("{}:{}: invalid time: {}", path, n + 1, fields[0]))?;
+        let index = find_param(params, num_params, fields[1]).map_err(|e| format!("{}:{}: {}", path, n + 1, e))?;
+        let value: f32 = fields[2].parse().map_err(|_| format!("{}:{}: invalid value: {}", path, n + 1, fields[2]))?;
+        ret.push(Automation {
+            frame: (seconds.max(0.0) * sr).round() as usize,
+            index: index,
+            value: value,
+        });
+    }
+
+    return Ok(ret);
+}
@@ -13,6 +13,7 @@ use std::collections::HashMap;
 use super::EffectParameters;
 use crate::logger::Logger;
 use crate::widgets::*;
+use crate::params::PARAMS;
 
 // === GLOBALS ===
 const WINDOW_WIDTH:  usize = 300;
@@ -60,9 +61,12 @@ impl Editor for EffectEditor {
         let app = Application::new(window_description, move |state, window|{
             state.add_theme(THEME);
 
-            CustomKnob::new(params.clone(), 0, "".to_string(), "".to_string(), "".to_string(), "".to_string() ).build(state, window, |builder| {
-                builder.set_width(Units::Pixels(100.0))
-            });
+            // one knob per parameter, see `params.rs`
+            for (i, spec) in PARAMS.iter().enumerate() {
+                CustomKnob::new(params.clone(), i as i32, spec.name.to_string(), spec.unit.to_string(), "".to_string(), "".to_string() ).build(state, window, |builder| {
+                    builder.set_width(Units::Pixels(100.0))
+                });
+            }
         });
         
         self.logger.log(&format!(">>> VstParent from parent pointer: {:?}.\n", parent)[..]);
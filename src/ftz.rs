@@ -0,0 +1,152 @@
+// === FLUSH-TO-ZERO ===
+// NOTE: denormal numbers are very slow to compute with on most CPUs, and IIR
+// filters and feedback loops produce lots of them as a signal decays towards
+// zero. With flush-to-zero (and denormals-are-zero on x86) the CPU treats them
+// as zero instead. The floating point mode belongs to the thread, which is the
+// host's audio thread, so it's only changed while processing and put back
+// exactly as it was afterwards.
+
+#[cfg(target_arch = "x86_64")]
+#[allow(deprecated)]
+use std::arch::x86_64::{_mm_getcsr, _mm_setcsr};
+#[cfg(target_arch = "aarch64")]
+use std::arch::asm;
+
+// whether `FtzGuard` does anything on this platform, when it doesn't the
+// denormal dithering is used instead
+pub const SUPPORTED: bool = cfg!(any(target_arch = "x86_64", target_arch = "aarch64"));
+
+#[cfg(target_arch = "x86_64")]
+const MXCSR_DAZ: u32 = 1 << 6;
+#[cfg(target_arch = "x86_64")]
+const MXCSR_FTZ: u32 = 1 << 15;
+#[cfg(target_arch = "aarch64")]
+const FPCR_FZ: u64 = 1 << 24;
+
+/// Enables flush-to-zero on the current thread until it's dropped, then
+/// restores the previous floating point mode.
+pub struct FtzGuard {
+    #[cfg(target_arch = "x86_64")]
+    prev_mxcsr: u32,
+    #[cfg(target_arch = "aarch64")]
+    prev_fpcr: u64,
+}
+
+impl FtzGuard {
+    #[cfg(target_arch = "x86_64")]
+    #[allow(deprecated)]
+    pub fn new() -> Self {
+        // SAFETY: SSE is always available on x86_64, and only the denormal
+        // handling bits are changed.
+        let prev_mxcsr = unsafe { _mm_getcsr() };
+        unsafe { _mm_setcsr(prev_mxcsr | MXCSR_DAZ | MXCSR_FTZ) };
+        Self { prev_mxcsr: prev_mxcsr }
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    pub fn new() -> Self {
+        let prev_fpcr: u64;
+        // SAFETY: reading and writing FPCR is allowed at EL0, and only the
+        // flush-to-zero bit is changed.
+        unsafe {
+            asm!("mrs {}, fpcr", out(reg) prev_fpcr, options(nomem, nostack));
+            asm!("msr fpcr, {}", in(reg) prev_fpcr | FPCR_FZ, options(nomem, nostack));
+        }
+        Self { prev_fpcr: prev_fpcr }
+    }
+
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Drop for FtzGuard {
+    fn drop(&mut self) {
+        #[cfg(target_arch = "x86_64")]
+        #[allow(deprecated)]
+        unsafe { _mm_setcsr(self.prev_mxcsr) };
+
+        #[cfg(target_arch = "aarch64")]
+        unsafe { asm!("msr fpcr, {}", in(reg) self.prev_fpcr, options(nomem, nostack)) };
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::hint::black_box;
+
+    // the floating point mode of the current thread, and the bits the guard sets
+    #[cfg(target_arch = "x86_64")]
+    #[allow(deprecated)]
+    fn mode() -> u64 {
+        // the low 6 bits are sticky exception flags, not part of the mode
+        unsafe { (_mm_getcsr() & !0x3f) as u64 }
+    }
+    #[cfg(target_arch = "x86_64")]
+    #[allow(deprecated)]
+    fn set_mode(mode: u64) {
+        unsafe { _mm_setcsr(mode as u32) };
+    }
+    #[cfg(target_arch = "x86_64")]
+    const FLUSH_BITS: u64 = (MXCSR_DAZ | MXCSR_FTZ) as u64;
+
+    #[cfg(target_arch = "aarch64")]
+    fn mode() -> u64 {
+        let fpcr: u64;
+        unsafe { asm!("mrs {}, fpcr", out(reg) fpcr, options(nomem, nostack)) };
+        fpcr
+    }
+    #[cfg(target_arch = "aarch64")]
+    fn set_mode(mode: u64) {
+        unsafe { asm!("msr fpcr, {}", in(reg) mode, options(nomem, nostack)) };
+    }
+    #[cfg(target_arch = "aarch64")]
+    const FLUSH_BITS: u64 = FPCR_FZ;
+
+    // half of the smallest normal number is a denormal, unless it's flushed
+    fn denormal() -> f64 {
+        black_box(f64::MIN_POSITIVE) * black_box(0.5)
+    }
+
+    #[test]
+    #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+    fn flushes_while_alive_and_restores_on_drop() {
+        let outer = mode();
+        set_mode(outer & !FLUSH_BITS);
+        let before = mode();
+        assert!(denormal() != 0.0);
+
+        {
+            let _guard = FtzGuard::new();
+            assert_eq!(mode() & FLUSH_BITS, FLUSH_BITS);
+            assert_eq!(mode() & !FLUSH_BITS, before);
+            assert_eq!(denormal(), 0.0);
+
+            // a nested guard puts back the flushing mode, not the original one
+            {
+                let _inner = FtzGuard::new();
+            }
+            assert_eq!(mode() & FLUSH_BITS, FLUSH_BITS);
+        }
+
+        assert_eq!(mode(), before);
+        assert!(denormal() != 0.0);
+        set_mode(outer);
+    }
+
+    #[test]
+    #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+    fn keeps_a_mode_that_already_flushes() {
+        let outer = mode();
+        set_mode(outer | FLUSH_BITS);
+        let before = mode();
+
+        drop(FtzGuard::new());
+
+        assert_eq!(mode(), before);
+        set_mode(outer);
+    }
+}
@@ -9,7 +9,10 @@ extern crate dsp_lab;
 
 // third-party libs
 use vst::buffer::AudioBuffer;
-use vst::plugin::{Category, Info, Plugin, PluginParameters, CanDo};
+use vst::api::Events;
+use vst::event::Event;
+use vst::plugin::{Category, Info, Plugin, PluginParameters, CanDo, HostCallback};
+use vst::channels::ChannelInfo;
 use vst::util::AtomicFloat;
 
 // my own libs
@@ -30,39 +33,101 @@ use std::fs::File;
 use std::io::prelude::*;
 use std::path::Path;
 use std::collections::HashMap;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicI32, AtomicU64, Ordering};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash, Hasher};
+use std::time::{SystemTime, UNIX_EPOCH};
 
 // internal dependencies
 mod process;
 mod logger;
 //mod editor;
 mod algo;
+mod state;
+mod presets;
+mod midi;
+mod sync;
+mod pitch;
+mod automation;
+mod params;
+mod ftz;
+mod oversample;
+#[cfg(test)]
+mod testing;
 //mod widgets;
 use crate::logger::Logger;
-use crate::algo::Dropouts;
+use crate::algo::{Dropouts, Tape, Envelope, Engine};
+use crate::presets::FACTORY_PRESETS;
+use crate::midi::MidiMessage;
+use crate::automation::ParamChange;
+use crate::params::{Param, PARAMS};
+use crate::ftz::FtzGuard;
+use crate::oversample::{Oversampler, SampleDelay};
 
 // === GLOBALS ===
 const DEBUG_LOGGING_ENABLED: bool = true;
 
 // === PARAMETERS ===
+// NOTE: the parameters themselves are declared in `params.rs`
 pub struct EffectParameters {
-    dict: HashMap<i32, AtomicFloat>,
+    values: Vec<AtomicFloat>,
+
+    // program management, the names can be changed by the host
+    preset_num: AtomicI32,
+    preset_names: Mutex<Vec<String>>,
+
+    // seed used when the seed control is on "instance", random for every new
+    // instance and saved with the session
+    instance_seed: AtomicU64,
 }
 
 impl Default for EffectParameters {
     fn default() -> Self {
-        let mut ret = Self {
-            dict: HashMap::new(),
-        };
-        ret.dict.insert(0, AtomicFloat::new(0.25));   // time
-        ret.dict.insert(1, AtomicFloat::new(0.0 ));   // vibe
-        ret.dict.insert(2, AtomicFloat::new(0.0 ));   // age
-        ret.dict.insert(3, AtomicFloat::new(0.8 ));   // tone
-        ret.dict.insert(4, AtomicFloat::new(0.5 ));   // pitch mode
-        ret.dict.insert(5, AtomicFloat::new(0.0 ));   // feedback
-        ret.dict.insert(6, AtomicFloat::new(0.0 ));   // saturation
-        ret.dict.insert(7, AtomicFloat::new(0.5 ));   // dry / wet
+        // values are looked up by `Param`, so the table has to be in the same order
+        debug_assert!(PARAMS.iter().enumerate().all(|(i, p)| p.id as usize == i));
+        Self {
+            values: PARAMS.iter().map(|p| AtomicFloat::new(p.default)).collect(),
+            preset_num: AtomicI32::new(0),
+            preset_names: Mutex::new(FACTORY_PRESETS.iter().map(|p| p.name.to_string()).collect()),
+            instance_seed: AtomicU64::new(random_seed()),
+        }
+    }
+}
+
+// a seed that's different for every instance, never 0 (see `algo::derive_seed`)
+fn random_seed() -> u64 {
+    let mut hasher = RandomState::new().build_hasher();
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos().hash(&mut hasher);
+    hasher.finish().max(1)
+}
+
+impl EffectParameters {
+    /// Normalized value of a parameter.
+    pub fn get(&self, id: Param) -> f32 {
+        self.values[id as usize].get()
+    }
+
+    /// Value of a parameter mapped onto its range, see `ParamSpec::scale`.
+    pub fn scaled(&self, id: Param) -> f64 {
+        PARAMS[id as usize].scale(self.get(id))
+    }
 
-        return ret;
+    /// Scaled value of an advanced parameter, `None` when it follows its macro.
+    pub fn micro(&self, id: Param) -> Option<f64> {
+        if self.get(id) > 0.0 {Some(self.scaled(id))} else {None}
+    }
+
+    /// Base seed of all the random sources, the seed control or the
+    /// instance's own seed when that's at the top. At 0 it's the fixed seed
+    /// every instance had before the seed existed.
+    pub fn seed(&self) -> u64 {
+        let seed = self.scaled(Param::Seed);
+        if seed >= PARAMS[Param::Seed as usize].max {
+            self.instance_seed.load(Ordering::Relaxed)
+        } else {
+            seed as u64
+        }
     }
 }
 
@@ -70,16 +135,20 @@ impl Default for EffectParameters {
 impl PluginParameters for EffectParameters {
     // the `get_parameter` function reads the value of a parameter.
     fn get_parameter(&self, index: i32) -> f32 {
-        match self.dict.get(&index) {
-            Some(p) => p.get(),
+        match params::spec(index) {
+            Some(_) => self.values[index as usize].get(),
             None => 0.0,
         }
     }
 
     // the `set_parameter` function sets the value of a parameter.
     fn set_parameter(&self, index: i32, val: f32) {
-        match self.dict.get(&index) {
-            Some(p) => p.set(val),
+        // a NaN from the host would end up in the parameter filters for good
+        if !val.is_finite() {
+            return;
+        }
+        match params::spec(index) {
+            Some(_) => self.values[index as usize].set(val.max(0.0).min(1.0)),
             None => (),
         };
     }
@@ -87,60 +156,114 @@ impl PluginParameters for EffectParameters {
     // This is what will display underneath our control.  We can
     // format it into a string that makes the most since.
     fn get_parameter_text(&self, index: i32) -> String {
-        let def_val = &AtomicFloat::new(0.0);
-        match index {
-            0 => format!("{:.2}", 
-                self.dict.get(&0).unwrap().get()),
-            1 => format!("{:.2}", 
-                self.dict.get(&1).unwrap().get()),
-            2 => format!("{:.2}", 
-                self.dict.get(&2).unwrap().get()),
-            3 => format!("{:.2}", 
-                self.dict.get(&3).unwrap().get()),
-            4 => format!("{}", 
-                match (self.dict.get(&4).unwrap().get() * 130.0).round() as u32 {
-                    0..=9     => "+7, -12",
-                    10..=19   => "-12, -12",
-                    20..=29   => "-5, -12",
-                    30..=39   => "+12, -12",
-                    40..=49   => "-5, -5",
-                    50..=59   => "0, -5",
-                    60..=69   => "0, 0",
-                    70..=79   => "+7, 0",
-                    80..=89   => "+7, +7",
-                    90..=99   => "+7, -5",
-                    100..=109 => "+12, +7",
-                    110..=119 => "+12, +12",
-                    _         => "+12, -5"
-            }),
-            5 => format!("{:.2} {}", 
-                self.dict.get(&5).unwrap().get(), 
-                if self.dict.get(&5).unwrap().get() >= 0.52 {"/!\\"} else {"   "}),
-            6 => format!("{:.2}", 
-                self.dict.get(&6).unwrap().get()),
-            7 => format!("{:.2}", 
-                self.dict.get(&7).unwrap().get()),
-            _ => "".to_string(),
+        match params::spec(index) {
+            Some(spec) => (spec.format)(spec, self.values[index as usize].get()),
+            None => "".to_string(),
+        }
+    }
+
+    // the unit, shown next to the value text
+    fn get_parameter_label(&self, index: i32) -> String {
+        match params::spec(index) {
+            Some(spec) => spec.unit,
+            None => "",
         }
+        .to_string()
     }
 
     // This shows the control's name.
     fn get_parameter_name(&self, index: i32) -> String {
-        match index {
-            0 => "time",
-            1 => "vibe",
-            2 => "age",
-            3 => "tone",
-            4 => "pitch",
-            5 => "feedback",
-            6 => "distortion",
-            7 => "moisture",
-            _ => "",
+        match params::spec(index) {
+            Some(spec) => spec.name,
+            None => "",
         }
         .to_string()
     }
 
-    // TODO: missing methods for preset management
+    // typed-in values, in the same form as `get_parameter_text`
+    fn string_to_parameter(&self, index: i32, text: String) -> bool {
+        let val = match params::spec(index) {
+            Some(spec) => (spec.parse)(spec, &text),
+            None => None,
+        };
+        match val {
+            Some(val) => {
+                self.values[index as usize].set(val);
+                true
+            },
+            None => false,
+        }
+    }
+
+    fn can_be_automated(&self, index: i32) -> bool {
+        match params::spec(index) {
+            Some(spec) => spec.automatable,
+            None => false,
+        }
+    }
+
+    // loading a program sets all eight macro controls at once, and puts the
+    // advanced page back on the macros so the program sounds as intended
+    fn change_preset(&self, preset: i32) {
+        if preset < 0 || preset as usize >= FACTORY_PRESETS.len() {
+            return;
+        }
+        let preset_data = &FACTORY_PRESETS[preset as usize];
+        for (i, spec) in PARAMS.iter().enumerate() {
+            if !presets::KEPT.contains(&spec.id) {
+                self.set_parameter(i as i32, spec.default);
+            }
+        }
+        for (i, val) in preset_data.values.iter().enumerate() {
+            self.set_parameter(i as i32, *val);
+        }
+        let engine = match preset_data.engine {
+            Engine::Classic => 0.0,
+            Engine::TriLine => 1.0,
+        };
+        self.set_parameter(Param::Engine as i32, engine);
+        self.preset_num.store(preset, Ordering::Relaxed);
+    }
+
+    fn get_preset_num(&self) -> i32 {
+        self.preset_num.load(Ordering::Relaxed)
+    }
+
+    fn set_preset_name(&self, name: String) {
+        let preset = self.preset_num.load(Ordering::Relaxed) as usize;
+        match self.preset_names.lock().unwrap().get_mut(preset) {
+            Some(n) => *n = name,
+            None => (),
+        };
+    }
+
+    fn get_preset_name(&self, preset: i32) -> String {
+        if preset < 0 {
+            return "".to_string();
+        }
+        match self.preset_names.lock().unwrap().get(preset as usize) {
+            Some(n) => n.clone(),
+            None => "".to_string(),
+        }
+    }
+
+    // the whole plugin state is stored in a single chunk, so that a project
+    // reopens exactly as it was saved, regardless of the host.
+    fn get_preset_data(&self) -> Vec<u8> {
+        state::serialize(self)
+    }
+
+    fn get_bank_data(&self) -> Vec<u8> {
+        state::serialize(self)
+    }
+
+    fn load_preset_data(&self, data: &[u8]) {
+        state::deserialize(self, data);
+    }
+
+    fn load_bank_data(&self, data: &[u8]) {
+        state::deserialize(self, data);
+    }
 }
 
 
@@ -156,13 +279,18 @@ pub struct Effect {
     // store a handle to the openned log file (None if debugging is disabled)
     logger: Arc<Logger>,
 
+    // handle to the host, None when running without one (e.g. vibe_render)
+    host: Option<HostCallback>,
+
     // meta variables
     sr: f64,
     scale: f64, // scaling factor for sr independence of integrals
+    tempo: f64, // last known host tempo, in bpm
 
     // delay lines
-    dly_l: DelayLine,
-    dly_r: DelayLine,
+    dly_l: Tape,
+    dly_r: Tape,
+    dly_c: Tape,    // only used by the 3 lines engine
     // combs_l: DelayLine,
     // combs_r: DelayLine,
 
@@ -171,6 +299,7 @@ pub struct Effect {
     lfo_2: ParOsc,
     lfo_3: ParOsc,
     lfo_4: ParOsc,
+    lfo_5: ParOsc,
 
     // flutter LFO's
     flut_tri_1: AsymTriOsc,
@@ -185,46 +314,103 @@ pub struct Effect {
     // dropouts
     drop_l: Dropouts,
     drop_r: Dropouts,
+    drop_c: Dropouts,
+
+    // tape noise, the hiss is band passed by subtracting the low end
+    hiss_l: NoiseWhite,
+    hiss_r: NoiseWhite,
+    hiss_lp_l: LowPass1P,
+    hiss_lp_r: LowPass1P,
+    hiss_low_l: LowPass1P,
+    hiss_low_r: LowPass1P,
+    mod_noise_l: NoiseWhite,
+    mod_noise_r: NoiseWhite,
+
+    // ducking
+    duck_env: Envelope,
 
     // variable positions
     left_pos:  f64,
     right_pos: f64,
+    // puts the positions back where they rest on the next sample, the resting
+    // positions depend on the smoothed time, which only the audio thread knows
+    heads_reset: bool,
 
     // filters
     block_dc_l: DcBlock,
     block_dc_r: DcBlock,
+    block_dc_c: DcBlock,
     tone_lp_l: LowPass1P,
     tone_lp_r: LowPass1P,
+    tone_lp_c: LowPass1P,
     fb_antialias_l_1: LowPass1P,
     fb_antialias_r_1: LowPass1P,
     fb_antialias_l_2: LowPass1P,
     fb_antialias_r_2: LowPass1P,
     fb_antialias_l_3: LowPass1P,
     fb_antialias_r_3: LowPass1P,
-
-    // param filters
-    param_1_lp: LowPass1P,
-    param_2_lp: LowPass1P,
-    param_3_lp: LowPass1P,
-    param_4_lp: LowPass1P,
-    param_5_lp: LowPass1P,
-    param_6_lp: LowPass1P,
-    param_7_lp: LowPass1P,
-    param_8_lp: LowPass1P,
-
-    // dithering
+    fb_antialias_c_1: LowPass1P,
+    fb_antialias_c_2: LowPass1P,
+    fb_antialias_c_3: LowPass1P,
+
+    // param filters, one per parameter in `PARAMS`
+    param_lp: Vec<LowPass1P>,
+
+    // oversampling of the saturation and the feedback clipper
+    os_sat_l: Oversampler,
+    os_sat_r: Oversampler,
+    os_sat_c: Oversampler,
+    os_clip_l: Oversampler,
+    os_clip_r: Oversampler,
+    os_clip_c: Oversampler,
+
+    // latency compensation, see `oversample::MAX_LATENCY`
+    dry_comp_l: SampleDelay,
+    dry_comp_r: SampleDelay,
+    wet_comp_l: SampleDelay,
+    wet_comp_r: SampleDelay,
+
+    // dithering, only used where flush-to-zero isn't available
     in_dith_l: DenormalDither,
     in_dith_r: DenormalDither,
     fb_dith_l: DenormalDither,
     fb_dith_r: DenormalDither,
+    fb_dith_c: DenormalDither,
 
     // toneerential variables
     fb_l: f64,
     fb_r: f64,
+    fb_c: f64,
+    // lengths of the frozen loops in samples, center, left and right, fixed
+    // while the freeze is on
+    frozen_c: usize,
+    frozen_l: usize,
+    frozen_r: usize,
 
     // hysteresis
     hyst_l: Hysteresis,
     hyst_r: Hysteresis,
+
+    // MIDI events for the current block
+    midi_events: Vec<MidiMessage>,
+
+    // time-stamped parameter changes, sorted by frame
+    param_changes: Vec<ParamChange>,
+
+    // wet gain, ramps back up after the DSP state is reset
+    fade_in: f64,
+
+    // energy of the input and of what's written onto the tape, and for how
+    // many samples it has stayed below silence, see `process::SILENCE`
+    energy: f64,
+    quiet_samples: usize,
+
+    // last known state of the host's transport, and of the panic control
+    playing: bool,
+    panic_held: bool,
+
+    // seed the random sources were last built with
+    seed: u64,
 }
 
 impl Default for Effect {
@@ -235,8 +421,8 @@ impl Default for Effect {
         palette.insert("knob background".to_string(), (0.2, 0.2, 0.2, 1.0));
         palette.insert("knob fill".to_string(), (0.9, 0.9, 0.9, 1.0));
         Effect {
-            // TODO: FIXME: achieve sample rate independence, this requires updating dsp_lab
-            // so that things can change their sr after being instantiated.
+            // NOTE: everything time-based is rebuilt for the host's sample rate
+            // in `set_sample_rate`, see `build_time_based`.
             params: params.clone(),
             /* editor: Some(EffectEditor {
                 logger: logger.clone(),
@@ -245,14 +431,17 @@ impl Default for Effect {
                 palette: Arc::new(palette),
             }), */
             logger: logger.clone(),
+            host: None,
 
             // meta variables
             sr: 44100.0,
             scale: 1.0,
+            tempo: 120.0,
 
             // delay lines
-            dly_l:   DelayLine::new(11000.0, 44100.0, InterpMethod::Quadratic, MixMethod::Average),
-            dly_r:   DelayLine::new(11000.0, 44100.0, InterpMethod::Quadratic, MixMethod::Average),
+            dly_l:   Tape::new(11000.0, 44100.0),
+            dly_r:   Tape::new(11000.0, 44100.0),
+            dly_c:   Tape::new(11000.0, 44100.0),
             //combs_l: DelayLine::new(2200.0,  44100.0, InterpMethod::Truncate,  MixMethod::Sqrt),
             //combs_r: DelayLine::new(2200.0,  44100.0, InterpMethod::Truncate,  MixMethod::Sqrt),
 
@@ -261,6 +450,7 @@ impl Default for Effect {
             lfo_2: ParOsc::new(0.0, 44100.0),
             lfo_3: ParOsc::new(0.0, 44100.0),
             lfo_4: ParOsc::new(0.0, 44100.0),
+            lfo_5: ParOsc::new(0.0, 44100.0),
 
             // flutter LFO's
             flut_tri_1: AsymTriOsc::new(0.0, 44100.0),
@@ -275,50 +465,279 @@ impl Default for Effect {
             // dropouts
             drop_l: Dropouts::new(),
             drop_r: Dropouts::new(),
+            drop_c: Dropouts::new(),
+
+            // tape noise
+            hiss_l: NoiseWhite::new(0),
+            hiss_r: NoiseWhite::new(0),
+            hiss_lp_l: LowPass1P::new(),
+            hiss_lp_r: LowPass1P::new(),
+            hiss_low_l: LowPass1P::new(),
+            hiss_low_r: LowPass1P::new(),
+            mod_noise_l: NoiseWhite::new(0),
+            mod_noise_r: NoiseWhite::new(0),
+
+            // ducking
+            duck_env: Envelope::new(44100.0),
 
             // variable positions
             left_pos: 0.0,
             right_pos: 0.0,
+            heads_reset: true,
 
             // filters
             block_dc_l: DcBlock::new(),
             block_dc_r: DcBlock::new(),
+            block_dc_c: DcBlock::new(),
             tone_lp_l: LowPass1P::new(),
             tone_lp_r: LowPass1P::new(),
+            tone_lp_c: LowPass1P::new(),
             fb_antialias_l_1: LowPass1P::new(),
             fb_antialias_r_1: LowPass1P::new(),
             fb_antialias_l_2: LowPass1P::new(),
             fb_antialias_r_2: LowPass1P::new(),
             fb_antialias_l_3: LowPass1P::new(),
             fb_antialias_r_3: LowPass1P::new(),
+            fb_antialias_c_1: LowPass1P::new(),
+            fb_antialias_c_2: LowPass1P::new(),
+            fb_antialias_c_3: LowPass1P::new(),
 
             // param filters
-            param_1_lp: LowPass1P::new(),
-            param_2_lp: LowPass1P::new(),
-            param_3_lp: LowPass1P::new(),
-            param_4_lp: LowPass1P::new(),
-            param_5_lp: LowPass1P::new(),
-            param_6_lp: LowPass1P::new(),
-            param_7_lp: LowPass1P::new(),
-            param_8_lp: LowPass1P::new(),
+            param_lp: PARAMS.iter().map(|_| LowPass1P::new()).collect(),
+
+            // oversampling
+            os_sat_l: Oversampler::new(),
+            os_sat_r: Oversampler::new(),
+            os_sat_c: Oversampler::new(),
+            os_clip_l: Oversampler::new(),
+            os_clip_r: Oversampler::new(),
+            os_clip_c: Oversampler::new(),
+
+            // latency compensation
+            dry_comp_l: SampleDelay::new(),
+            dry_comp_r: SampleDelay::new(),
+            wet_comp_l: SampleDelay::new(),
+            wet_comp_r: SampleDelay::new(),
 
             // dithering
             in_dith_l: DenormalDither::new(3),
             in_dith_r: DenormalDither::new(4),
             fb_dith_l: DenormalDither::new(5),
             fb_dith_r: DenormalDither::new(6),
+            fb_dith_c: DenormalDither::new(7),
 
             // differential variables
             fb_l: 0.0,
             fb_r: 0.0,
+            fb_c: 0.0,
+            frozen_c: 0,
+            frozen_l: 0,
+            frozen_r: 0,
 
             // hysteresis
             hyst_l: Hysteresis::new(),
             hyst_r: Hysteresis::new(),
+
+            // MIDI
+            midi_events: Vec::with_capacity(midi::MAX_EVENTS),
+
+            // automation
+            param_changes: Vec::with_capacity(automation::MAX_CHANGES),
+
+            fade_in: 1.0,
+            energy: 0.0,
+            quiet_samples: 0,
+            playing: false,
+            panic_held: false,
+            seed: 0,
         }
     }
 }
 
+impl Effect {
+    /// Schedule a parameter change on an exact sample, counted from the start
+    /// of the next block to be processed. Changes past the end of that block
+    /// carry over to the following ones, so the result doesn't depend on the
+    /// block size. Returns false if the queue is full.
+    pub fn queue_parameter_change(&mut self, frame: usize, index: i32, value: f32) -> bool {
+        if self.param_changes.len() >= automation::MAX_CHANGES {
+            return false;
+        }
+        let at = self.param_changes.iter().position(|c| c.frame > frame).unwrap_or(self.param_changes.len());
+        self.param_changes.insert(at, ParamChange {
+            frame: frame,
+            index: index,
+            value: value,
+        });
+        return true;
+    }
+
+    // Smooth a scaled parameter value with its filter, parameters without
+    // smoothing pass through untouched.
+    fn smooth(&mut self, id: Param, val: f64) -> f64 {
+        if PARAMS[id as usize].smoothing > 0.0 {
+            self.param_lp[id as usize].step(val)
+        } else {
+            val
+        }
+    }
+
+    // An advanced parameter, its macro mapping unless overridden. The override
+    // is smoothed starting from the macro, so switching to it doesn't jump.
+    fn micro(&mut self, id: Param, over: Option<f64>, macro_val: f64) -> f64 {
+        let smoothed = self.smooth(id, over.unwrap_or(macro_val));
+        if over.is_some() {smoothed} else {macro_val}
+    }
+
+    // Puts the tape, filters and feedback back to silence, as if the plugin had
+    // just been loaded, and fades the wet signal back in. LFO's and parameters
+    // are left as they are. This doesn't allocate, so it's safe to call from
+    // the audio thread.
+    fn reset_dsp(&mut self) {
+        let sr = self.sr;
+
+        self.dly_l.clear();
+        self.dly_r.clear();
+        self.dly_c.clear();
+        self.fb_l = 0.0;
+        self.fb_r = 0.0;
+        self.fb_c = 0.0;
+        self.heads_reset = true;
+
+        // filters
+        self.block_dc_l = DcBlock::new();
+        self.block_dc_r = DcBlock::new();
+        self.block_dc_c = DcBlock::new();
+        self.tone_lp_l = LowPass1P::new();
+        self.tone_lp_r = LowPass1P::new();
+        self.tone_lp_c = LowPass1P::new();
+        self.block_dc_l.set_sr(sr);
+        self.block_dc_r.set_sr(sr);
+        self.block_dc_c.set_sr(sr);
+        self.tone_lp_l.set_sr(sr);
+        self.tone_lp_r.set_sr(sr);
+        self.tone_lp_c.set_sr(sr);
+        for lp in [
+            &mut self.fb_antialias_l_1, &mut self.fb_antialias_l_2, &mut self.fb_antialias_l_3,
+            &mut self.fb_antialias_r_1, &mut self.fb_antialias_r_2, &mut self.fb_antialias_r_3,
+            &mut self.fb_antialias_c_1, &mut self.fb_antialias_c_2, &mut self.fb_antialias_c_3,
+        ].iter_mut() {
+            **lp = LowPass1P::new();
+            lp.set_sr(sr);
+            lp.set_cutoff(15000.0);
+        }
+        self.hyst_l = Hysteresis::new();
+        self.hyst_r = Hysteresis::new();
+
+        // tape hiss, most of it between 800 Hz and 8 kHz
+        for lp in [&mut self.hiss_lp_l, &mut self.hiss_lp_r].iter_mut() {
+            **lp = LowPass1P::new();
+            lp.set_sr(sr);
+            lp.set_cutoff(8000.0);
+        }
+        for lp in [&mut self.hiss_low_l, &mut self.hiss_low_r].iter_mut() {
+            **lp = LowPass1P::new();
+            lp.set_sr(sr);
+            lp.set_cutoff(800.0);
+        }
+
+        self.duck_env = Envelope::new(sr);
+
+        // oversampling and latency compensation
+        self.os_sat_l = Oversampler::new();
+        self.os_sat_r = Oversampler::new();
+        self.os_sat_c = Oversampler::new();
+        self.os_clip_l = Oversampler::new();
+        self.os_clip_r = Oversampler::new();
+        self.os_clip_c = Oversampler::new();
+        self.dry_comp_l = SampleDelay::new();
+        self.dry_comp_r = SampleDelay::new();
+        self.wet_comp_l = SampleDelay::new();
+        self.wet_comp_r = SampleDelay::new();
+
+        self.energy = 0.0;
+        self.fade_in = 0.0;
+    }
+
+    // Delay lines and LFO's can't change their sample rate after being
+    // instantiated, so they are rebuilt from scratch with the current sample
+    // rate. This allocates, so it must never be called from the audio thread.
+    fn build_time_based(&mut self) {
+        let sr = self.sr;
+
+        // delay lines, each is read by a center head and a side head
+        self.dly_l = Tape::new(11000.0, sr);
+        self.dly_r = Tape::new(11000.0, sr);
+        self.dly_c = Tape::new(11000.0, sr);
+
+        self.build_random_sources();
+    }
+
+    // Everything random or free-running, started from the current seed, see
+    // `algo::derive_seed`. This doesn't allocate, so it's also called from the
+    // audio thread when the seed changes.
+    fn build_random_sources(&mut self) {
+        let sr = self.sr;
+        let seed = self.params.seed();
+
+        // wow LFO's, they all have mutually irrational ratios betweem them, so
+        // that they never fully sync up.
+        self.lfo_1 = ParOsc::new(algo::derive_phase(seed, 20), sr);
+        self.lfo_2 = ParOsc::new(algo::derive_phase(seed, 21), sr);
+        self.lfo_3 = ParOsc::new(algo::derive_phase(seed, 22), sr);
+        self.lfo_4 = ParOsc::new(algo::derive_phase(seed, 23), sr);
+        self.lfo_5 = ParOsc::new(algo::derive_phase(seed, 24), sr);
+        self.lfo_1.set_freq(0.4506093942819681745120095823784220832585749031233);
+        self.lfo_2.set_freq(0.6517664324912187283319554965534637881637093311621);
+        self.lfo_3.set_freq(0.6224960938630510854555394309830762427824365504454);
+        self.lfo_4.set_freq(0.8546512878312836353100107896170289708260075021792);
+        self.lfo_5.set_freq(0.734038095294905);     // lfo_1 * e/2.7 * phi, for the center line
+
+        // flutter LFO's, they all have mutually irrational ratios between them,
+        // so that they never fully sync up.
+        // To be precise: 
+        // - tri_1 is 0.125 * e/2.7
+        // - tri_2 is tri_1 * phi * e/2.7
+        // - tri_3 is tri_2 * phi * e/2.7
+        // - tri_4 is tri_3 * phi * e/2.7
+        // - tri_5 is tri_4 * phi * e/2.7
+        // I don't remember where I got the other two.        
+        self.flut_tri_1 = AsymTriOsc::new(algo::derive_phase(seed, 25), sr);
+        self.flut_tri_2 = AsymTriOsc::new(algo::derive_phase(seed, 26), sr);
+        self.flut_tri_3 = AsymTriOsc::new(algo::derive_phase(seed, 27), sr);
+        self.flut_tri_4 = AsymTriOsc::new(algo::derive_phase(seed, 28), sr);
+        self.flut_tri_5 = AsymTriOsc::new(algo::derive_phase(seed, 29), sr);
+        self.flut_sin_1 = ParOsc::new(algo::derive_phase(seed, 30), sr);
+        self.flut_sin_2 = ParOsc::new(algo::derive_phase(seed, 31), sr);
+        self.flut_tri_1.set_freq(0.8861641217884205093282427772342256043383834);
+        self.flut_tri_2.set_freq(0.5439961232435288973996703154361133102831035);
+        self.flut_tri_3.set_freq(0.3339469234059613632977919366094348203201041);
+        self.flut_tri_4.set_freq(0.2050024676414522912256778335030753190313349);
+        self.flut_tri_5.set_freq(0.1258463809471780201555688644144751156369095);
+        self.flut_sin_1.set_freq(5.5372407616758321234567890132435842678934068);
+        self.flut_sin_2.set_freq(8.9594437562828531234567891011121314151617181);
+
+        // dropouts
+        self.drop_l.rebuild(sr, seed);
+        self.drop_r.rebuild(sr, seed);
+        self.drop_c.rebuild(sr, seed);
+
+        // noise
+        self.flut_scrape = NoiseWhite::new(algo::derive_seed(seed, 0));
+        self.in_dith_l = DenormalDither::new(algo::derive_seed(seed, 3));
+        self.in_dith_r = DenormalDither::new(algo::derive_seed(seed, 4));
+        self.fb_dith_l = DenormalDither::new(algo::derive_seed(seed, 5));
+        self.fb_dith_r = DenormalDither::new(algo::derive_seed(seed, 6));
+        self.fb_dith_c = DenormalDither::new(algo::derive_seed(seed, 7));
+        self.hiss_l = NoiseWhite::new(algo::derive_seed(seed, 12));
+        self.hiss_r = NoiseWhite::new(algo::derive_seed(seed, 13));
+        self.mod_noise_l = NoiseWhite::new(algo::derive_seed(seed, 14));
+        self.mod_noise_r = NoiseWhite::new(algo::derive_seed(seed, 15));
+
+        self.seed = seed;
+    }
+}
+
 // All plugins using `vst` also need to implement the `Plugin` trait.  Here, we
 // define functions that give necessary info to our host.
 impl Plugin for Effect {
@@ -329,19 +748,41 @@ impl Plugin for Effect {
             vendor: "Flux-Audio".to_string(),
             unique_id: 4751486,
             version: 010,
-            inputs: 2,
+            presets: FACTORY_PRESETS.len() as i32,
+            inputs: 4,  // the second pair is the sidechain for the ducking
             outputs: 2,
+            midi_inputs: 1,
             // This `parameters` bit is important; without it, none of our
             // parameters will be shown!
-            parameters: 8,
+            parameters: PARAMS.len() as i32,
             category: Category::Effect,
-            initial_delay: 0,
+            // NOTE: the host can't be told when this changes, so it's always
+            // the latency of the highest oversampling factor, see `process.rs`
+            initial_delay: oversample::MAX_LATENCY as i32,
+            preset_chunks: true,
             ..Default::default()
         };
 
         return nfo;
     }
 
+    fn get_input_info(&self, input: i32) -> ChannelInfo {
+        let (name, short) = match input {
+            0 => ("Input L", "In L"),
+            1 => ("Input R", "In R"),
+            2 => ("Sidechain L", "SC L"),
+            _ => ("Sidechain R", "SC R"),
+        };
+        ChannelInfo::new(name.to_string(), Some(short.to_string()), true, None)
+    }
+
+    fn new(host: HostCallback) -> Self {
+        Effect {
+            host: Some(host),
+            ..Default::default()
+        }
+    }
+
     fn set_sample_rate(&mut self, rate: f32) {
         self.logger.log(&format!("Plugin::set_sample_rate() callback with rate: {}\n", rate)[..]);
         self.sr = rate as f64;
@@ -349,66 +790,39 @@ impl Plugin for Effect {
 
         self.block_dc_l.set_sr(rate as f64);
         self.block_dc_r.set_sr(rate as f64);
+        self.block_dc_c.set_sr(rate as f64);
         self.tone_lp_l.set_sr(rate as f64);
         self.tone_lp_r.set_sr(rate as f64);
+        self.tone_lp_c.set_sr(rate as f64);
         self.fb_antialias_l_1.set_sr(rate as f64);
         self.fb_antialias_r_1.set_sr(rate as f64);
         self.fb_antialias_l_2.set_sr(rate as f64);
         self.fb_antialias_r_2.set_sr(rate as f64);
         self.fb_antialias_l_3.set_sr(rate as f64);
         self.fb_antialias_r_3.set_sr(rate as f64);
+        self.fb_antialias_c_1.set_sr(rate as f64);
+        self.fb_antialias_c_2.set_sr(rate as f64);
+        self.fb_antialias_c_3.set_sr(rate as f64);
+        self.hiss_lp_l.set_sr(rate as f64);
+        self.hiss_lp_r.set_sr(rate as f64);
+        self.hiss_low_l.set_sr(rate as f64);
+        self.hiss_low_r.set_sr(rate as f64);
+        self.duck_env = Envelope::new(rate as f64);
 
         // param filters
-        self.param_1_lp.set_sr(rate as f64);
-        self.param_2_lp.set_sr(rate as f64);
-        self.param_3_lp.set_sr(rate as f64);
-        self.param_4_lp.set_sr(rate as f64);
-        self.param_5_lp.set_sr(rate as f64);
-        self.param_6_lp.set_sr(rate as f64);
-        self.param_7_lp.set_sr(rate as f64);
-        self.param_8_lp.set_sr(rate as f64);
+        for lp in self.param_lp.iter_mut() {
+            lp.set_sr(rate as f64);
+        }
+
+        // delay lines and LFO's
+        self.build_time_based();
     }
 
     // called once
     fn init(&mut self) {
         self.logger.log("Plugin::init() callback!\n");
 
-        self.dly_l.add_head(500.0, 1.0);
-        self.dly_l.add_head(500.0, 1.0);
-        self.dly_r.add_head(500.0, 1.0);
-        self.dly_r.add_head(500.0, 1.0);
-
-        /*
-        for _ in 0..tone_TAPS_L.len(){
-            self.combs_l.add_head(500.0, 1.0);
-            self.combs_r.add_head(500.0, 1.0);
-        }
-        */
-
-        // wow LFO's, they all have mutually irrational ratios betweem them, so
-        // that they never fully sync up.
-        self.lfo_1.set_freq(0.4506093942819681745120095823784220832585749031233);
-        self.lfo_2.set_freq(0.6517664324912187283319554965534637881637093311621);
-        self.lfo_3.set_freq(0.6224960938630510854555394309830762427824365504454);
-        self.lfo_4.set_freq(0.8546512878312836353100107896170289708260075021792);
-
-        // flutter LFO's, they all have mutually irrational ratios between them,
-        // so that they never fully sync up.
-        // To be precise: 
-        // - tri_1 is 0.125 * e/2.7
-        // - tri_2 is tri_1 * phi * e/2.7
-        // - tri_3 is tri_2 * phi * e/2.7
-        // - tri_4 is tri_3 * phi * e/2.7
-        // - tri_5 is tri_4 * phi * e/2.7
-        // I don't remember where I got the other two.        
-        self.flut_tri_1.set_freq(0.8861641217884205093282427772342256043383834);
-        self.flut_tri_2.set_freq(0.5439961232435288973996703154361133102831035);
-        self.flut_tri_3.set_freq(0.3339469234059613632977919366094348203201041);
-        self.flut_tri_4.set_freq(0.2050024676414522912256778335030753190313349);
-        self.flut_tri_5.set_freq(0.1258463809471780201555688644144751156369095);
-        self.flut_sin_1.set_freq(5.5372407616758321234567890132435842678934068);
-        self.flut_sin_2.set_freq(8.9594437562828531234567891011121314151617181);
-
+        self.build_time_based();
 
         // Filters
         self.fb_antialias_l_1.set_cutoff(15000.0);
@@ -417,25 +831,21 @@ impl Plugin for Effect {
         self.fb_antialias_r_2.set_cutoff(15000.0);
         self.fb_antialias_l_3.set_cutoff(15000.0);
         self.fb_antialias_r_3.set_cutoff(15000.0);
-
-
-        // dropout LFO's
-        // They are mutually irrational, so they never sync up
-        // - drop_1 is  7 * e/2.7
-        // - drop_2 is 11 * e/2.7 * e/2.7
-        //self.drop_1.set_freq(7.04739733304196912871185640721060647566693690959);
-        //self.drop_2.set_freq(11.1494673646415847050116189391392436138523280214);
-
-        // param filters
+        self.fb_antialias_c_1.set_cutoff(15000.0);
+        self.fb_antialias_c_2.set_cutoff(15000.0);
+        self.fb_antialias_c_3.set_cutoff(15000.0);
+        self.hiss_lp_l.set_cutoff(8000.0);
+        self.hiss_lp_r.set_cutoff(8000.0);
+        self.hiss_low_l.set_cutoff(800.0);
+        self.hiss_low_r.set_cutoff(800.0);
+
+        // param filters, unsmoothed parameters keep theirs unused
         // TODO: tune these to maximize sweep speed without artifacts
-        self.param_1_lp.set_cutoff(0.5);
-        self.param_2_lp.set_cutoff(2.0);
-        self.param_3_lp.set_cutoff(7.5);
-        self.param_4_lp.set_cutoff(20.0);
-        self.param_5_lp.set_cutoff(20.0);
-        self.param_6_lp.set_cutoff(20.0);
-        self.param_7_lp.set_cutoff(20.0);
-        self.param_8_lp.set_cutoff(20.0);
+        for (lp, spec) in self.param_lp.iter_mut().zip(PARAMS.iter()) {
+            if spec.smoothing > 0.0 {
+                lp.set_cutoff(spec.smoothing);
+            }
+        }
     }
 
     /*
@@ -456,7 +866,7 @@ impl Plugin for Effect {
         use vst::plugin::CanDo::*;
 
         match can_do {
-            SendEvents | ReceiveEvents => Yes,
+            ReceiveEvents | ReceiveMidiEvent | ReceiveTimeInfo => Yes,
             _ => No,
         }
     }
@@ -469,24 +879,253 @@ impl Plugin for Effect {
         Arc::clone(&self.params) as Arc<dyn PluginParameters>
     }
 
+    // in samples, see `process::tail_size`
+    fn get_tail_size(&self) -> isize {
+        process::tail_size(self)
+    }
+
     fn resume(&mut self) {
         self.logger.log("Plugin::resume() callback!\n");
+
+        if self.params.get(Param::ClearOnStop) >= 0.5 {
+            self.reset_dsp();
+        }
     }
 
     fn suspend(&mut self) {
         self.logger.log("Plugin::suspend() callback!\n");
     }
 
+    // MIDI events are stored and applied at their exact sample position in
+    // `process_chunk`, see `midi.rs` for the mapping.
+    fn process_events(&mut self, events: &Events) {
+        for e in events.events() {
+            match e {
+                Event::Midi(ev) => {
+                    if self.midi_events.len() < midi::MAX_EVENTS {
+                        self.midi_events.push(MidiMessage {
+                            frame: ev.delta_frames.max(0) as usize,
+                            data: ev.data,
+                        });
+                    }
+                },
+                _ => (),
+            }
+        }
+    }
+
     // Here is where the bulk of our audio processing code goes.
     fn process(&mut self, buffer: &mut AudioBuffer<f32>) {
         // === pre-process setup ===
-        // TODO: store previous value of flush-to-zero and disable
+        // flush-to-zero is on for the whole block, the host's floating point
+        // mode is restored when the guard goes out of scope
+        let _ftz = FtzGuard::new();
 
         process::process_chunk(self, buffer);
-
-        // === post-process cleanup ===
-        // TODO: after processing, restore previous value of flush-to-zero
     }
 }
 
-plugin_main!(Effect);
\ No newline at end of file
+plugin_main!(Effect);
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use vst::host::HostBuffer;
+    use crate::testing;
+
+    const RATES: [f64; 4] = [44100.0, 48000.0, 96000.0, 192000.0];
+
+    // times in seconds at which a signal crosses the middle of its range
+    // going up, or changes value when `any_change` is set
+    fn events<F: FnMut() -> f64>(mut next: F, sr: f64, secs: f64, any_change: bool) -> Vec<f64> {
+        let signal: Vec<f64> = (0..(secs * sr) as usize).map(|_| next()).collect();
+        let lo = signal.iter().cloned().fold(f64::INFINITY, f64::min);
+        let hi = signal.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+        let mid = (lo + hi) * 0.5;
+        (1..signal.len())
+            .filter(|i| if any_change {signal[*i] != signal[i - 1]} else {signal[i - 1] < mid && signal[*i] >= mid})
+            .map(|i| i as f64 / sr)
+            .take(4)
+            .collect()
+    }
+
+    #[test]
+    fn first_repeat_lands_on_time_at_any_rate() {
+        for sr in RATES.iter() {
+            let mut effect = testing::effect(*sr);
+            // the defaults have no wow, flutter, dropouts, pitch shifting or
+            // feedback, so the first repeat is a clean copy of the burst
+            testing::set(&effect, Param::Time, 0.1);
+            testing::set(&effect, Param::Moisture, 1.0);
+            testing::set(&effect, Param::Tone, 1.0);
+            let time = effect.params.scaled(Param::Time);
+
+            // long enough for the time and moisture smoothing to settle
+            let settle = (4.0 * sr) as usize;
+            let expected = (time * 0.001 * sr).round() as usize + oversample::MAX_LATENCY;
+            // a 1 ms burst, an impulse would be smeared by the tone filter
+            // differently at each rate
+            let mut input = vec![0.0f32; settle + expected * 2];
+            for s in input[settle..settle + (0.001 * sr) as usize].iter_mut() {
+                *s = 1.0;
+            }
+            let out = testing::render(&mut effect, &[input.clone(), input], 2, 512);
+
+            let onset = testing::onset(&out[0][settle..], 0.1).unwrap_or(0);
+            assert!((onset as isize - expected as isize).abs() <= 1, "{} Hz: repeat at {} samples, expected {}", sr, onset, expected);
+        }
+    }
+
+    #[test]
+    fn modulation_periods_follow_sample_rate() {
+        let measure = |sr: f64| {
+            let mut effect = testing::effect(sr);
+            (
+                events(|| effect.lfo_1.step(), sr, 20.0, false),
+                events(|| effect.flut_tri_1.step(), sr, 20.0, false),
+                events(|| effect.drop_l.next_gain(), sr, 20.0, true),
+            )
+        };
+        let reference = measure(RATES[0]);
+        assert!(reference.0.len() >= 2 && reference.1.len() >= 2 && reference.2.len() >= 2);
+        for sr in RATES[1..].iter() {
+            let other = measure(*sr);
+            for (a, b) in [(&reference.0, &other.0), (&reference.1, &other.1), (&reference.2, &other.2)].iter() {
+                assert_eq!(a.len(), b.len(), "{} Hz", sr);
+                for (ta, tb) in a.iter().zip(b.iter()) {
+                    assert!((ta - tb).abs() < 1e-3, "{} Hz: {} s against {} s at 44.1 kHz", sr, tb, ta);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn same_result_for_any_block_size() {
+        let sr = 16000.0;
+        let secs = |t: f64| (t * sr) as usize;
+
+        // a noise burst, a silence longer than the tape so that the silence
+        // skip kicks in, then another burst
+        let mut rng = 1u64;
+        let mut noise = || {
+            rng ^= rng << 13;
+            rng ^= rng >> 7;
+            rng ^= rng << 17;
+            (rng >> 40) as f32 / (1u64 << 24) as f32 - 0.5
+        };
+        // on a block boundary, so that the skip can be checked there
+        let second = secs(16.0) / 512 * 512;
+        let mut input = vec![0.0f32; second + secs(1.5)];
+        for i in (0..secs(0.5)).chain(second..second + secs(0.5)) {
+            input[i] = noise();
+        }
+        let (first_part, second_part) = input.split_at(second);
+
+        // automation at odd positions, some of it while the skip is on
+        let first_changes = [
+            (secs(0.1) + 7, Param::Time, 0.01),
+            (secs(0.3) + 3, Param::Seed, 0.25),
+            (secs(0.4) + 11, Param::Pitch, 0.8),
+            (secs(14.0) + 5, Param::Time, 0.02),
+            (secs(14.5) + 1, Param::Seed, 0.75),
+        ];
+        let second_changes = [
+            (13, Param::Freeze, 1.0),
+            (secs(1.0) + 9, Param::Freeze, 0.0),
+        ];
+
+        let run = |block: usize| {
+            let mut effect = testing::effect(sr);
+            testing::set(&effect, Param::Time, 0.0);
+            testing::set(&effect, Param::Vibe, 0.6);
+            testing::set(&effect, Param::Age, 0.7);
+            testing::set(&effect, Param::Feedback, 0.15);
+            testing::set(&effect, Param::Engine, 1.0);
+            let mut out = testing::render_automated(&mut effect, &[first_part.to_vec(), first_part.to_vec()], 2, block, &first_changes);
+            assert!(effect.quiet_samples >= effect.dly_l.len(), "the silence skip never started");
+            let rest = testing::render_automated(&mut effect, &[second_part.to_vec(), second_part.to_vec()], 2, block, &second_changes);
+            for (o, r) in out.iter_mut().zip(rest.iter()) {
+                o.extend_from_slice(r);
+            }
+            out
+        };
+
+        let reference = run(1);
+        for block in [64, 512].iter() {
+            let out = run(*block);
+            for ch in 0..2 {
+                if let Some(i) = (0..input.len()).find(|i| out[ch][*i] != reference[ch][*i]) {
+                    panic!("block {}: channel {} differs at sample {}: {} against {}", block, ch, i, out[ch][i], reference[ch][i]);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn preset_resets_what_it_doesnt_list() {
+        let effect = testing::effect(44100.0);
+        testing::set(&effect, Param::PitchMode, 1.0);
+        testing::set(&effect, Param::Freeze, 1.0);
+        testing::set(&effect, Param::Flutter, 0.5);
+        testing::set(&effect, Param::Oversampling, 1.0);
+        let seed = effect.params.get(Param::Seed);
+
+        // Octave Shimmer Loop, which needs the classic pitch mode for its +12, +12
+        effect.params.change_preset(2);
+        for spec in PARAMS.iter().skip(8) {
+            let expected = match spec.id {
+                Param::Engine => 1.0,
+                Param::Oversampling => 1.0,
+                Param::Seed => seed,
+                _ => spec.default,
+            };
+            assert_eq!(effect.params.get(spec.id), expected, "{}", spec.name);
+        }
+        assert_eq!(effect.params.get_parameter_text(Param::Pitch as i32), "+12, +12");
+    }
+
+    #[test]
+    fn ring_out_does_not_get_slower() {
+        let sr = 44100.0;
+        let block = 512;
+        let mut effect = testing::effect(sr);
+        // a feedback just past unity keeps the loop ringing quietly for the
+        // whole test, so the tape chain is never skipped, while the ducking
+        // envelope, which only hears the silent input, decays through the
+        // denormal range
+        testing::set(&effect, Param::Feedback, 0.52);
+        testing::set(&effect, Param::Tone, 1.0);
+        testing::set(&effect, Param::Moisture, 1.0);
+
+        let noise: Vec<f32> = (0..block).map(|i| ((i * 7919) % 1000) as f32 / 500.0 - 1.0).collect();
+        let silence = vec![0.0f32; block];
+        let mut outs = vec![vec![0.0f32; block]; 2];
+        let mut host_buffer: HostBuffer<f32> = HostBuffer::new(2, 2);
+
+        // four minutes of ring-out after a short burst, each block timed
+        let blocks = (240.0 * sr) as usize / block;
+        let mut times = Vec::with_capacity(blocks);
+        for b in 0..blocks {
+            let input: &[f32] = if b < 20 {&noise} else {&silence};
+            let ins = [input, input];
+            let mut out_refs: Vec<&mut [f32]> = outs.iter_mut().map(|o| &mut o[..]).collect();
+            let mut buffer = host_buffer.bind(&ins, &mut out_refs);
+            let start = std::time::Instant::now();
+            effect.process(&mut buffer);
+            times.push(start.elapsed().as_secs_f64());
+        }
+        assert!(outs[0].iter().any(|s| s.abs() > 1e-4), "the loop stopped ringing");
+
+        // medians over ten seconds, right after the burst and at the very end
+        let median = |t: &[f64]| {
+            let mut t = t.to_vec();
+            t.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            t[t.len() / 2]
+        };
+        let span = (10.0 * sr) as usize / block;
+        let early = median(&times[20..20 + span]);
+        let late = median(&times[blocks - span..]);
+        assert!(late < early * 4.0, "{:.1} us per block at the end against {:.1} us at the start", late * 1e6, early * 1e6);
+    }
+}
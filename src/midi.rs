@@ -0,0 +1,43 @@
+// === GLOBALS ===
+// Maximum number of MIDI events handled per block, the event buffer is
+// allocated once so that the audio thread never allocates.
+pub const MAX_EVENTS: usize = 512;
+
+// CC numbers mapped to the eight controls, in parameter order. 20 to 27 are
+// undefined in the MIDI spec, so they don't clash with anything.
+const CC_FIRST: u8 = 20;
+const CC_LAST:  u8 = 27;
+
+// "all sound off" from the MIDI spec
+const CC_ALL_SOUND_OFF: u8 = 120;
+
+#[derive(Clone, Copy)]
+pub struct MidiMessage {
+    pub frame: usize,
+    pub data: [u8; 3],
+}
+
+pub enum MidiAction {
+    // set a parameter to a normalized value
+    Param(i32, f32),
+    // put the read heads back to their resting positions
+    ResetHeads,
+    // load a program
+    Program(i32),
+    // erase the tape and everything else that holds sound
+    Panic,
+    None,
+}
+
+/// Translate a raw MIDI message into what it does to the plugin, all channels
+/// are treated the same.
+pub fn parse(data: [u8; 3]) -> MidiAction {
+    match data[0] & 0xF0 {
+        0xB0 if data[1] >= CC_FIRST && data[1] <= CC_LAST =>
+            MidiAction::Param((data[1] - CC_FIRST) as i32, data[2] as f32 / 127.0),
+        0xB0 if data[1] == CC_ALL_SOUND_OFF => MidiAction::Panic,
+        0x90 if data[2] > 0 => MidiAction::ResetHeads,
+        0xC0 => MidiAction::Program(data[1] as i32),
+        _ => MidiAction::None,
+    }
+}
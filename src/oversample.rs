@@ -0,0 +1,288 @@
+// stl stuff
+use std::f64::consts;
+
+// === OVERSAMPLING ===
+// NOTE: each 2x stage is a 65 tap halfband FIR, used once to upsample and once
+// to downsample. In a halfband filter every other tap is zero except the
+// center one, so each stage only computes the 32 odd taps per input sample.
+// The stages are cascaded for 4x and 8x, the later stages run at a higher rate
+// so their latency counts for less at the base rate.
+
+const HALF: usize = 32;         // nonzero taps besides the center, and the center's index
+const MAX_STAGES: usize = 3;    // 8x
+const MAX_FACTOR: usize = 1 << MAX_STAGES;
+
+// latency of the whole chain at the highest factor, in samples at the base rate
+pub const MAX_LATENCY: usize = 2 * HALF * (MAX_FACTOR - 1) / MAX_FACTOR;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Oversampling {
+    Off,
+    X2,
+    X4,
+    X8,
+}
+
+impl Oversampling {
+    pub fn from_param(val: f32) -> Self {
+        match (val * 3.0).round() as u32 {
+            0 => Oversampling::Off,
+            1 => Oversampling::X2,
+            2 => Oversampling::X4,
+            _ => Oversampling::X8,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Oversampling::Off => "off",
+            Oversampling::X2  => "2x",
+            Oversampling::X4  => "4x",
+            Oversampling::X8  => "8x",
+        }
+    }
+
+    fn stages(&self) -> usize {
+        match self {
+            Oversampling::Off => 0,
+            Oversampling::X2  => 1,
+            Oversampling::X4  => 2,
+            Oversampling::X8  => 3,
+        }
+    }
+
+    /// Latency of going up and back down, in samples at the base rate. Stage
+    /// k adds 2 * HALF samples at 2^k times the base rate.
+    pub fn latency(&self) -> usize {
+        let factor = 1 << self.stages();
+        2 * HALF * (factor - 1) / factor
+    }
+}
+
+// odd taps of a Blackman windowed halfband lowpass, normalized for unity gain
+// at DC (the center tap is 0.5, so the odd ones add up to the other half)
+fn halfband_taps() -> [f64; HALF] {
+    let len = (2 * HALF) as f64;
+    let mut taps = [0.0; HALF];
+    for k in 0..HALF {
+        let j = 2 * k + 1;
+        let x = (j as f64 - HALF as f64) * 0.5;
+        let sinc = (consts::PI * x).sin() / (consts::PI * x);
+        let phase = 2.0 * consts::PI * j as f64 / len;
+        let window = 0.42 - 0.5 * phase.cos() + 0.08 * (2.0 * phase).cos();
+        taps[k] = 0.5 * sinc * window;
+    }
+    let sum: f64 = taps.iter().sum();
+    for t in taps.iter_mut() {
+        *t *= 0.5 / sum;
+    }
+    return taps;
+}
+
+// one input sample in, two out
+struct HalfbandUp {
+    hist: [f64; HALF],  // newest first
+}
+
+impl HalfbandUp {
+    fn step(&mut self, taps: &[f64; HALF], x: f64) -> (f64, f64) {
+        self.hist.copy_within(0..HALF - 1, 1);
+        self.hist[0] = x;
+        // the zero stuffing halves the level, hence the factor 2
+        let even = self.hist[HALF / 2];
+        let odd: f64 = taps.iter().zip(self.hist.iter()).map(|(t, x)| t * x).sum();
+        (even, odd * 2.0)
+    }
+}
+
+// two input samples in, one out, keeping the even phase so that the latency is
+// a whole number of samples
+struct HalfbandDown {
+    even: [f64; HALF / 2 + 1],  // newest first
+    odd: [f64; HALF + 1],       // newest first
+}
+
+impl HalfbandDown {
+    fn step(&mut self, taps: &[f64; HALF], x_even: f64, x_odd: f64) -> f64 {
+        self.even.copy_within(0..HALF / 2, 1);
+        self.even[0] = x_even;
+        self.odd.copy_within(0..HALF, 1);
+        self.odd[0] = x_odd;
+        let sum: f64 = taps.iter().zip(self.odd[1..].iter()).map(|(t, x)| t * x).sum();
+        self.even[HALF / 2] * 0.5 + sum
+    }
+}
+
+/// Runs a nonlinear function at a multiple of the sample rate, one sample at
+/// a time. Allocation-free, the filters of every stage are always there so the
+/// factor can change at any time.
+pub struct Oversampler {
+    taps: [f64; HALF],
+    up: [HalfbandUp; MAX_STAGES],
+    down: [HalfbandDown; MAX_STAGES],
+}
+
+impl Oversampler {
+    pub fn new() -> Self {
+        Self {
+            taps: halfband_taps(),
+            up: [
+                HalfbandUp { hist: [0.0; HALF] },
+                HalfbandUp { hist: [0.0; HALF] },
+                HalfbandUp { hist: [0.0; HALF] },
+            ],
+            down: [
+                HalfbandDown { even: [0.0; HALF / 2 + 1], odd: [0.0; HALF + 1] },
+                HalfbandDown { even: [0.0; HALF / 2 + 1], odd: [0.0; HALF + 1] },
+                HalfbandDown { even: [0.0; HALF / 2 + 1], odd: [0.0; HALF + 1] },
+            ],
+        }
+    }
+
+    pub fn process<F: FnMut(f64) -> f64>(&mut self, x: f64, factor: Oversampling, mut f: F) -> f64 {
+        let stages = factor.stages();
+        if stages == 0 {
+            return f(x);
+        }
+
+        let mut buf = [0.0; MAX_FACTOR];
+        let mut tmp = [0.0; MAX_FACTOR];
+        let mut n = 1;
+        buf[0] = x;
+
+        // === upsample ===
+        for s in 0..stages {
+            for i in 0..n {
+                let (a, b) = self.up[s].step(&self.taps, buf[i]);
+                tmp[2 * i] = a;
+                tmp[2 * i + 1] = b;
+            }
+            n *= 2;
+            buf[..n].copy_from_slice(&tmp[..n]);
+        }
+
+        for y in buf[..n].iter_mut() {
+            *y = f(*y);
+        }
+
+        // === downsample ===
+        for s in (0..stages).rev() {
+            n /= 2;
+            for i in 0..n {
+                tmp[i] = self.down[s].step(&self.taps, buf[2 * i], buf[2 * i + 1]);
+            }
+            buf[..n].copy_from_slice(&tmp[..n]);
+        }
+
+        return buf[0];
+    }
+}
+
+/// A short delay in whole samples, to line up paths with different latencies.
+pub struct SampleDelay {
+    buf: [f64; 2 * MAX_LATENCY + 1],
+    pos: usize,
+}
+
+impl SampleDelay {
+    pub fn new() -> Self {
+        Self {
+            buf: [0.0; 2 * MAX_LATENCY + 1],
+            pos: 0,
+        }
+    }
+
+    pub fn step(&mut self, x: f64, delay: usize) -> f64 {
+        let len = self.buf.len();
+        self.buf[self.pos] = x;
+        let ret = self.buf[(self.pos + len - delay.min(len - 1)) % len];
+        self.pos = (self.pos + 1) % len;
+        return ret;
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FACTORS: [Oversampling; 4] = [
+        Oversampling::Off,
+        Oversampling::X2,
+        Oversampling::X4,
+        Oversampling::X8,
+    ];
+
+    fn render<F: FnMut(f64) -> f64 + Copy>(input: &[f64], factor: Oversampling, f: F) -> Vec<f64> {
+        let mut os = Oversampler::new();
+        input.iter().map(|x| os.process(*x, factor, f)).collect()
+    }
+
+    #[test]
+    fn latency_matches_impulse_response() {
+        for factor in FACTORS.iter() {
+            let mut impulse = vec![0.0; 128];
+            impulse[0] = 1.0;
+            let out = render(&impulse, *factor, |x| x);
+
+            // the filters are symmetric, so the peak sits on the group delay
+            let peak = (0..out.len())
+                .max_by(|a, b| out[*a].abs().partial_cmp(&out[*b].abs()).unwrap())
+                .unwrap();
+            assert_eq!(peak, factor.latency(), "{} impulse", factor.name());
+        }
+    }
+
+    #[test]
+    fn latency_matches_sine_delay() {
+        // a sine well inside the passband comes out delayed by exactly the latency
+        let freq = 0.05;
+        let input: Vec<f64> = (0..1024).map(|i| (2.0 * consts::PI * freq * i as f64).sin()).collect();
+        for factor in FACTORS.iter() {
+            let out = render(&input, *factor, |x| x);
+            let lat = factor.latency();
+            let err = (512..1024)
+                .map(|i| (out[i] - input[i - lat]).abs())
+                .fold(0.0, f64::max);
+            assert!(err < 1e-3, "{} is off by {}", factor.name(), err);
+        }
+    }
+
+    // power of one bin of the DFT
+    fn bin_power(signal: &[f64], bin: usize) -> f64 {
+        let w = 2.0 * consts::PI * bin as f64 / signal.len() as f64;
+        let (mut re, mut im) = (0.0, 0.0);
+        for (i, x) in signal.iter().enumerate() {
+            re += x * (w * i as f64).cos();
+            im += x * (w * i as f64).sin();
+        }
+        re * re + im * im
+    }
+
+    #[test]
+    fn oversampling_reduces_aliasing() {
+        // a sine on a whole number of periods, so that every harmonic lands on
+        // a bin: the 7th, 9th and 5th harmonics fold back onto these bins at
+        // the base rate, and none of them is a harmonic itself
+        const N: usize = 4096;
+        const SIGNAL: usize = 532;
+        const ALIASES: [usize; 3] = [372, 692, 1436];
+        let input: Vec<f64> = (0..2 * N)
+            .map(|i| 0.9 * (2.0 * consts::PI * (SIGNAL * i) as f64 / N as f64).sin())
+            .collect();
+
+        // aliases relative to the fundamental, in dB
+        let aliasing = |factor: Oversampling| {
+            let out = render(&input, factor, |x| (3.0 * x).tanh());
+            let tail = &out[N..];
+            let alias: f64 = ALIASES.iter().map(|b| bin_power(tail, *b)).sum();
+            10.0 * (alias / bin_power(tail, SIGNAL)).log10()
+        };
+
+        let off = aliasing(Oversampling::Off);
+        for factor in FACTORS[1..].iter() {
+            let on = aliasing(*factor);
+            assert!(on < off - 40.0, "{}: {:.1} dB, off: {:.1} dB", factor.name(), on, off);
+        }
+    }
+}
@@ -0,0 +1,474 @@
+// stl stuff
+use std::f64::consts;
+
+// internal dependencies
+use crate::sync::SyncMode;
+use crate::pitch::{self, PitchMode};
+use crate::algo::{WrapMode, Engine, StereoMode, DuckSource};
+use crate::oversample::Oversampling;
+
+// === PARAMETER TABLE ===
+// NOTE: this is the only place where parameters are declared. The host
+// callbacks, the DSP scaling, the smoothing filters, the state chunks and the
+// editor are all generated from `PARAMS`. To add a parameter, add a variant to
+// `Param` and an entry at the same position in `PARAMS`.
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Param {
+    Time = 0,
+    Vibe,
+    Age,
+    Tone,
+    Pitch,
+    Feedback,
+    Distortion,
+    Moisture,
+    Sync,
+    Freeze,
+    Wrap,
+    Engine,
+    PitchMode,
+    PitchL,
+    PitchR,
+    Oversampling,
+    ClearOnStop,
+    Panic,
+    Seed,
+    Flutter,
+    Dropouts,
+    WowCenter,
+    WowSide,
+    Noise,
+    Scrape,
+    Width,
+    StereoMode,
+    Duck,
+    DuckAttack,
+    DuckRelease,
+    DuckSource,
+}
+
+// how the normalized value is mapped onto the range
+#[derive(Clone, Copy, PartialEq)]
+pub enum Taper {
+    Linear,
+    // normalized value raised to a power before scaling
+    Power(i32),
+    // a switch with this many positions, scales to the position index
+    Stepped(u32),
+}
+
+pub struct ParamSpec {
+    pub id: Param,
+    pub name: &'static str,
+    pub default: f32,   // normalized
+    pub min: f64,
+    pub max: f64,
+    pub unit: &'static str,    // also the label shown next to the value
+    pub taper: Taper,
+    pub format: fn(&ParamSpec, f32) -> String,  // display text from the normalized value
+    pub parse: fn(&ParamSpec, &str) -> Option<f32>, // normalized value from typed-in text
+    pub smoothing: f64, // cutoff of the smoothing filter in Hz, 0 for none
+    pub automatable: bool,
+}
+
+impl ParamSpec {
+    /// Map a normalized value onto the parameter's range, as used by the DSP.
+    pub fn scale(&self, val: f32) -> f64 {
+        let val = val as f64;
+        match self.taper {
+            Taper::Linear   => self.min + val * (self.max - self.min),
+            Taper::Power(n) => self.min + val.powi(n) * (self.max - self.min),
+            Taper::Stepped(n) => (val * (n - 1) as f64).round(),
+        }
+    }
+
+    /// Map a value in the parameter's range back to a normalized value, the
+    /// inverse of `scale`.
+    pub fn unscale(&self, val: f64) -> f32 {
+        let norm = match self.taper {
+            Taper::Linear   => (val - self.min) / (self.max - self.min),
+            Taper::Power(n) => ((val - self.min) / (self.max - self.min)).max(0.0).powf(1.0 / n as f64),
+            Taper::Stepped(n) => val.round() / (n - 1) as f64,
+        };
+        norm.max(0.0).min(1.0) as f32
+    }
+}
+
+// one entry per variant of `Param`, the last one is `DuckSource`
+pub const NUM_PARAMS: usize = Param::DuckSource as usize + 1;
+
+pub static PARAMS: [ParamSpec; NUM_PARAMS] = [
+    ParamSpec {
+        id: Param::Time, name: "time", default: 0.25,
+        min: 50.0, max: 4500.0, unit: "ms", taper: Taper::Linear,
+        format: fmt_time, parse: parse_number, smoothing: 0.5, automatable: true,
+    },
+    ParamSpec {
+        id: Param::Vibe, name: "vibe", default: 0.0,
+        min: 0.0, max: 1.0, unit: "", taper: Taper::Linear,
+        format: fmt_plain, parse: parse_number, smoothing: 2.0, automatable: true,
+    },
+    ParamSpec {
+        id: Param::Age, name: "age", default: 0.0,
+        min: 0.0, max: 1.0, unit: "", taper: Taper::Linear,
+        format: fmt_plain, parse: parse_number, smoothing: 7.5, automatable: true,
+    },
+    ParamSpec {
+        id: Param::Tone, name: "tone", default: 0.8,
+        min: 0.0, max: 18000.0, unit: "Hz", taper: Taper::Power(4),
+        format: fmt_rounded, parse: parse_number, smoothing: 20.0, automatable: true,
+    },
+    ParamSpec {
+        // the 13 classic pitch presets, see `pitch.rs`
+        id: Param::Pitch, name: "pitch", default: 0.5,
+        min: 0.0, max: 12.0, unit: "", taper: Taper::Stepped(13),
+        format: fmt_classic_pitch, parse: parse_choice, smoothing: 0.0, automatable: true,
+    },
+    ParamSpec {
+        id: Param::Feedback, name: "feedback", default: 0.0,
+        min: 0.0, max: 2.0, unit: "dB", taper: Taper::Linear,
+        format: fmt_feedback, parse: parse_decibels, smoothing: 20.0, automatable: true,
+    },
+    ParamSpec {
+        id: Param::Distortion, name: "distortion", default: 0.0,
+        min: 0.125, max: 6.125, unit: "x", taper: Taper::Linear,
+        format: fmt_plain, parse: parse_number, smoothing: 20.0, automatable: true,
+    },
+    ParamSpec {
+        id: Param::Moisture, name: "moisture", default: 0.5,
+        min: 0.0, max: 100.0, unit: "%", taper: Taper::Linear,
+        format: fmt_rounded, parse: parse_number, smoothing: 20.0, automatable: true,
+    },
+    ParamSpec {
+        id: Param::Sync, name: "sync", default: 0.0,
+        min: 0.0, max: 3.0, unit: "", taper: Taper::Stepped(4),
+        format: fmt_sync, parse: parse_choice, smoothing: 0.0, automatable: true,
+    },
+    ParamSpec {
+        // the smoothing is the crossfade in and out of the frozen loop
+        id: Param::Freeze, name: "freeze", default: 0.0,
+        min: 0.0, max: 1.0, unit: "", taper: Taper::Stepped(2),
+        format: fmt_on_off, parse: parse_choice, smoothing: 5.0, automatable: true,
+    },
+    ParamSpec {
+        id: Param::Wrap, name: "wrap", default: 0.0,
+        min: 0.0, max: 2.0, unit: "", taper: Taper::Stepped(3),
+        format: fmt_wrap, parse: parse_choice, smoothing: 0.0, automatable: true,
+    },
+    ParamSpec {
+        // classic, so that old sessions sound the same, presets opt into 3 lines
+        id: Param::Engine, name: "engine", default: 0.0,
+        min: 0.0, max: 1.0, unit: "", taper: Taper::Stepped(2),
+        format: fmt_engine, parse: parse_choice, smoothing: 0.0, automatable: false,
+    },
+    ParamSpec {
+        id: Param::PitchMode, name: "pitch mode", default: 0.0,
+        min: 0.0, max: 4.0, unit: "", taper: Taper::Stepped(5),
+        format: fmt_pitch_mode, parse: parse_choice, smoothing: 0.0, automatable: true,
+    },
+    ParamSpec {
+        id: Param::PitchL, name: "pitch L", default: 0.5,
+        min: -pitch::MAX_SEMITONES, max: pitch::MAX_SEMITONES, unit: "st", taper: Taper::Linear,
+        format: fmt_semitones, parse: parse_semitones, smoothing: 0.0, automatable: true,
+    },
+    ParamSpec {
+        id: Param::PitchR, name: "pitch R", default: 0.5,
+        min: -pitch::MAX_SEMITONES, max: pitch::MAX_SEMITONES, unit: "st", taper: Taper::Linear,
+        format: fmt_semitones, parse: parse_semitones, smoothing: 0.0, automatable: true,
+    },
+    ParamSpec {
+        // for the saturation and the feedback clipper
+        id: Param::Oversampling, name: "oversampling", default: 0.0,
+        min: 0.0, max: 3.0, unit: "", taper: Taper::Stepped(4),
+        format: fmt_oversampling, parse: parse_choice, smoothing: 0.0, automatable: false,
+    },
+    ParamSpec {
+        // erase the tape when the host's transport starts or stops
+        id: Param::ClearOnStop, name: "clear on stop", default: 0.0,
+        min: 0.0, max: 1.0, unit: "", taper: Taper::Stepped(2),
+        format: fmt_on_off, parse: parse_choice, smoothing: 0.0, automatable: true,
+    },
+    ParamSpec {
+        // erases the tape every time it's switched on
+        id: Param::Panic, name: "panic", default: 0.0,
+        min: 0.0, max: 1.0, unit: "", taper: Taper::Stepped(2),
+        format: fmt_on_off, parse: parse_choice, smoothing: 0.0, automatable: true,
+    },
+    ParamSpec {
+        // 0 is the fixed seed of sessions from before the seed existed, the
+        // top of the range the instance's own seed, see `EffectParameters::seed`
+        id: Param::Seed, name: "seed", default: 1.0,
+        min: 0.0, max: 1000.0, unit: "", taper: Taper::Stepped(1001),
+        format: fmt_seed, parse: parse_seed, smoothing: 0.0, automatable: true,
+    },
+    // NOTE: the ranges of the advanced page cover what their macro can reach
+    ParamSpec {
+        // age * age
+        id: Param::Flutter, name: "flutter", default: 0.0,
+        min: 0.0, max: 1.0, unit: "", taper: Taper::Linear,
+        format: fmt_micro, parse: parse_micro, smoothing: 7.5, automatable: true,
+    },
+    ParamSpec {
+        // age
+        id: Param::Dropouts, name: "dropouts", default: 0.0,
+        min: 0.0, max: 1.0, unit: "", taper: Taper::Linear,
+        format: fmt_micro, parse: parse_micro, smoothing: 7.5, automatable: true,
+    },
+    ParamSpec {
+        // vibe * 8 ms
+        id: Param::WowCenter, name: "wow center", default: 0.0,
+        min: 0.0, max: 8.0, unit: "ms", taper: Taper::Linear,
+        format: fmt_micro, parse: parse_micro, smoothing: 2.0, automatable: true,
+    },
+    ParamSpec {
+        // vibe * 10 ms
+        id: Param::WowSide, name: "wow side", default: 0.0,
+        min: 0.0, max: 10.0, unit: "ms", taper: Taper::Linear,
+        format: fmt_micro, parse: parse_micro, smoothing: 2.0, automatable: true,
+    },
+    ParamSpec {
+        // level of the tape hiss and modulation noise
+        id: Param::Noise, name: "noise", default: 0.0,
+        min: 0.0, max: 100.0, unit: "%", taper: Taper::Linear,
+        format: fmt_rounded, parse: parse_number, smoothing: 20.0, automatable: true,
+    },
+    ParamSpec {
+        // adds the scrape flutter to the flutter
+        id: Param::Scrape, name: "scrape", default: 0.0,
+        min: 0.0, max: 1.0, unit: "", taper: Taper::Stepped(2),
+        format: fmt_on_off, parse: parse_choice, smoothing: 0.0, automatable: true,
+    },
+    ParamSpec {
+        // of the wet signal, 0 is mono and 100 leaves it as it is
+        id: Param::Width, name: "width", default: 0.5,
+        min: 0.0, max: 200.0, unit: "%", taper: Taper::Linear,
+        format: fmt_rounded, parse: parse_number, smoothing: 20.0, automatable: true,
+    },
+    ParamSpec {
+        id: Param::StereoMode, name: "stereo mode", default: 0.0,
+        min: 0.0, max: 1.0, unit: "", taper: Taper::Stepped(2),
+        format: fmt_stereo_mode, parse: parse_choice, smoothing: 0.0, automatable: true,
+    },
+    ParamSpec {
+        // how far the wet signal is turned down while the input plays
+        id: Param::Duck, name: "duck", default: 0.0,
+        min: 0.0, max: 100.0, unit: "%", taper: Taper::Linear,
+        format: fmt_rounded, parse: parse_number, smoothing: 20.0, automatable: true,
+    },
+    ParamSpec {
+        id: Param::DuckAttack, name: "duck attack", default: 0.3,
+        min: 0.1, max: 100.0, unit: "ms", taper: Taper::Power(2),
+        format: fmt_plain, parse: parse_number, smoothing: 0.0, automatable: true,
+    },
+    ParamSpec {
+        id: Param::DuckRelease, name: "duck release", default: 0.35,
+        min: 10.0, max: 2000.0, unit: "ms", taper: Taper::Power(2),
+        format: fmt_rounded, parse: parse_number, smoothing: 0.0, automatable: true,
+    },
+    ParamSpec {
+        id: Param::DuckSource, name: "duck source", default: 0.0,
+        min: 0.0, max: 1.0, unit: "", taper: Taper::Stepped(2),
+        format: fmt_duck_source, parse: parse_choice, smoothing: 0.0, automatable: true,
+    },
+];
+
+/// Look up the spec of a parameter by its host index.
+pub fn spec(index: i32) -> Option<&'static ParamSpec> {
+    if index < 0 {
+        return None;
+    }
+    PARAMS.get(index as usize)
+}
+
+
+// === DISPLAY FORMATTERS ===
+// NOTE: the unit is not part of the text, hosts show it from the label.
+
+fn fmt_plain(spec: &ParamSpec, val: f32) -> String {
+    format!("{:.2}", spec.scale(val))
+}
+
+fn fmt_rounded(spec: &ParamSpec, val: f32) -> String {
+    format!("{:.0}", spec.scale(val))
+}
+
+// the center delay, followed by the resting times of the left and right heads,
+// which are derived from the rounded center so that the text reads back exactly
+fn fmt_time(spec: &ParamSpec, val: f32) -> String {
+    let time = spec.scale(val).round();
+    format!("{:.0} ({:.0}, {:.0})", time, time * consts::LOG2_E, time * consts::LOG2_E * consts::LOG2_E)
+}
+
+fn fmt_feedback(spec: &ParamSpec, val: f32) -> String {
+    let gain = spec.scale(val);
+    let db = if gain > 0.0 {format!("{:+.1}", 20.0 * gain.log10())} else {"-inf".to_string()};
+    // above this the loop self-oscillates
+    format!("{} {}", db, if val >= 0.52 {"/!\\"} else {"   "})
+}
+
+fn fmt_on_off(_spec: &ParamSpec, val: f32) -> String {
+    (if val >= 0.5 {"on"} else {"off"}).to_string()
+}
+
+fn fmt_classic_pitch(_spec: &ParamSpec, val: f32) -> String {
+    pitch::classic_name(val).to_string()
+}
+
+fn fmt_semitones(spec: &ParamSpec, val: f32) -> String {
+    format!("{:+.2}", spec.scale(val))
+}
+
+fn fmt_sync(_spec: &ParamSpec, val: f32) -> String {
+    SyncMode::from_param(val).name().to_string()
+}
+
+fn fmt_wrap(_spec: &ParamSpec, val: f32) -> String {
+    WrapMode::from_param(val).name().to_string()
+}
+
+fn fmt_engine(_spec: &ParamSpec, val: f32) -> String {
+    Engine::from_param(val).name().to_string()
+}
+
+fn fmt_stereo_mode(_spec: &ParamSpec, val: f32) -> String {
+    StereoMode::from_param(val).name().to_string()
+}
+
+fn fmt_duck_source(_spec: &ParamSpec, val: f32) -> String {
+    DuckSource::from_param(val).name().to_string()
+}
+
+fn fmt_pitch_mode(_spec: &ParamSpec, val: f32) -> String {
+    PitchMode::from_param(val).name().to_string()
+}
+
+fn fmt_seed(spec: &ParamSpec, val: f32) -> String {
+    match spec.scale(val) {
+        seed if seed <= spec.min => "classic".to_string(),
+        seed if seed >= spec.max => "instance".to_string(),
+        seed => format!("{}", seed as u32),
+    }
+}
+
+// "macro" at 0, otherwise the override
+fn fmt_micro(spec: &ParamSpec, val: f32) -> String {
+    if val > 0.0 {fmt_plain(spec, val)} else {"macro".to_string()}
+}
+
+fn fmt_oversampling(_spec: &ParamSpec, val: f32) -> String {
+    Oversampling::from_param(val).name().to_string()
+}
+
+
+// === TEXT PARSERS ===
+// NOTE: these accept whatever the formatter of the same parameter displays, so
+// that a value can be typed back in as it's shown.
+
+fn squash(text: &str) -> String {
+    text.split_whitespace().collect::<String>().to_lowercase()
+}
+
+// A number in the parameter's unit, which can be typed in with the unit, e.g.
+// "750ms" or "2.5 kHz". A percentage of a parameter with another unit is taken
+// as a fraction of the range. Anything after the first number in brackets, like
+// the head times of `time`, is ignored.
+fn parse_number(spec: &ParamSpec, text: &str) -> Option<f32> {
+    let text = squash(text);
+    let text = text.split('(').next().unwrap_or("");
+    let unit = spec.unit.to_lowercase();
+    if unit != "%" {
+        if let Some(num) = text.strip_suffix('%') {
+            return num.parse::<f64>().ok().map(|v| (v / 100.0).max(0.0).min(1.0) as f32);
+        }
+    }
+    let (num, mult) = match text.strip_suffix(&unit[..]) {
+        Some(num) if !unit.is_empty() => match num.strip_suffix('k') {
+            Some(num) => (num, 1000.0),
+            None => (num, 1.0),
+        },
+        _ => (text, 1.0),
+    };
+    num.parse::<f64>().ok().map(|v| spec.unscale(v * mult))
+}
+
+// feedback gain in dB, "-inf" for no feedback
+fn parse_decibels(spec: &ParamSpec, text: &str) -> Option<f32> {
+    let text = squash(text);
+    let text = text.trim_end_matches("/!\\");
+    let text = text.strip_suffix("db").unwrap_or(text);
+    if text == "-inf" {
+        return Some(spec.unscale(0.0));
+    }
+    text.parse::<f64>().ok().map(|db| spec.unscale(10f64.powf(db / 20.0)))
+}
+
+// semitones, with or without the unit
+fn parse_semitones(spec: &ParamSpec, text: &str) -> Option<f32> {
+    let text = squash(text);
+    let num = text.strip_suffix(spec.unit).unwrap_or(&text);
+    num.parse::<f64>().ok().map(|v| spec.unscale(v))
+}
+
+// the name of one of the positions of a switch, e.g. "dotted" or "+7, -5"
+fn parse_choice(spec: &ParamSpec, text: &str) -> Option<f32> {
+    let positions = match spec.taper {
+        Taper::Stepped(n) => n,
+        _ => return None,
+    };
+    let text = squash(text);
+    (0..positions)
+        .map(|i| i as f32 / (positions - 1) as f32)
+        .find(|val| squash(&(spec.format)(spec, *val)) == text)
+}
+
+// "classic", "instance" or a seed number
+fn parse_seed(spec: &ParamSpec, text: &str) -> Option<f32> {
+    let text = squash(text);
+    match text.as_str() {
+        "classic" => return Some(0.0),
+        "instance" => return Some(1.0),
+        _ => (),
+    }
+    text.parse::<u32>().ok().map(|v| spec.unscale(v as f64))
+}
+
+// "macro" or a number, which is kept just above 0 so that it stays an override
+// even at the bottom of the range
+fn parse_micro(spec: &ParamSpec, text: &str) -> Option<f32> {
+    if squash(text) == "macro" {
+        return Some(0.0);
+    }
+    parse_number(spec, text).map(|val| val.max(f32::EPSILON))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn table_is_in_param_order() {
+        for (i, spec) in PARAMS.iter().enumerate() {
+            assert_eq!(spec.id as usize, i, "\"{}\" is not at its `Param` index", spec.name);
+        }
+    }
+
+    #[test]
+    fn seed_text_round_trips() {
+        let spec = &PARAMS[Param::Seed as usize];
+        for text in ["classic", "1", "500", "999", "instance"].iter() {
+            let val = (spec.parse)(spec, text).unwrap();
+            assert_eq!((spec.format)(spec, val), *text);
+        }
+        assert_eq!((spec.format)(spec, spec.default), "instance");
+    }
+
+    #[test]
+    fn new_instances_roll_their_own_seed() {
+        let params = crate::EffectParameters::default();
+        assert_eq!(params.seed(), params.instance_seed.load(std::sync::atomic::Ordering::Relaxed));
+        params.values[Param::Seed as usize].set(0.0);
+        assert_eq!(params.seed(), 0);
+    }
+}
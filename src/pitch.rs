@@ -0,0 +1,107 @@
+// === PITCH SHIFTING ===
+// NOTE: a shift is how much faster (positive) or slower (negative) than the
+// tape a read head moves, so a shift of 1.0 is an octave up and -0.5 is an
+// octave down.
+
+// The 13 classic presets, all permutations of shifting the left and right
+// channels by a fifth or an octave. The shifts are the original rounded values
+// (0.5 for a fifth up), not the exact ratios, so old sessions sound the same.
+const CLASSIC: [(&str, f64, f64); 13] = [
+    ("+7, -12",  0.5,  -0.5),
+    ("-12, -12", -0.5,  -0.5),
+    ("-5, -12",  -0.25, -0.5),
+    ("+12, -12", 1.0,  -0.5),
+    ("-5, -5",   -0.25, -0.25),
+    ("0, -5",    0.0,  -0.25),
+    ("0, 0",     0.0,   0.0),
+    ("+7, 0",    0.5,   0.0),
+    ("+7, +7",   0.5,   0.5),
+    ("+7, -5",   0.5,  -0.25),
+    ("+12, +7",  1.0,   0.5),
+    ("+12, +12", 1.0,   1.0),
+    ("+12, -5",  1.0,  -0.25),
+];
+
+// semitones in an octave that belong to each scale
+const MAJOR: [f64; 7] = [0.0, 2.0, 4.0, 5.0, 7.0, 9.0, 11.0];
+const MINOR: [f64; 7] = [0.0, 2.0, 3.0, 5.0, 7.0, 8.0, 10.0];
+
+// range of the continuous pitch controls, in semitones
+pub const MAX_SEMITONES: f64 = 24.0;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum PitchMode {
+    // the 13 fixed presets of the `pitch` control
+    Classic,
+    // continuous left and right pitch, no snapping
+    Free,
+    // continuous pitch snapped to a scale
+    Chromatic,
+    Major,
+    Minor,
+}
+
+impl PitchMode {
+    pub fn from_param(val: f32) -> Self {
+        match (val * 4.0).round() as u32 {
+            0 => PitchMode::Classic,
+            1 => PitchMode::Free,
+            2 => PitchMode::Chromatic,
+            3 => PitchMode::Major,
+            _ => PitchMode::Minor,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PitchMode::Classic   => "classic",
+            PitchMode::Free      => "free",
+            PitchMode::Chromatic => "chromatic",
+            PitchMode::Major     => "major",
+            PitchMode::Minor     => "minor",
+        }
+    }
+}
+
+fn classic_index(val: f32) -> usize {
+    (((val * 130.0).round() as u32 / 10) as usize).min(CLASSIC.len() - 1)
+}
+
+/// Label of the classic preset selected by the `pitch` control.
+pub fn classic_name(val: f32) -> &'static str {
+    CLASSIC[classic_index(val)].0
+}
+
+/// Left and right shifts of the classic preset selected by the `pitch` control.
+pub fn classic_shifts(val: f32) -> (f64, f64) {
+    let (_, l, r) = CLASSIC[classic_index(val)];
+    (l, r)
+}
+
+/// Snap semitones to the closest note of the scale of the given mode.
+pub fn snap(semis: f64, mode: PitchMode) -> f64 {
+    let scale: &[f64] = match mode {
+        PitchMode::Chromatic => return semis.round(),
+        PitchMode::Major => &MAJOR,
+        PitchMode::Minor => &MINOR,
+        _ => return semis,
+    };
+
+    let octave = (semis / 12.0).floor() * 12.0;
+    let mut best = semis;
+    let mut best_dist = f64::INFINITY;
+    // the next octave's root is a candidate too
+    for note in scale.iter().chain([12.0].iter()) {
+        let dist = (semis - octave - note).abs();
+        if dist < best_dist {
+            best = octave + note;
+            best_dist = dist;
+        }
+    }
+    return best;
+}
+
+/// Convert semitones into a shift.
+pub fn shift(semis: f64) -> f64 {
+    (semis / 12.0).exp2() - 1.0
+}
@@ -0,0 +1,76 @@
+use crate::algo::Engine;
+use crate::params::Param;
+
+// === FACTORY PRESETS ===
+// NOTE: values are normalized, in the same order as the parameters:
+// time, vibe, age, tone, pitch, feedback, distortion, moisture
+// The pitch values sit in the middle of each of the 13 pitch modes, see
+// `get_parameter_text` for the mapping.
+// Every preset runs on the classic engine unless it asks for 3 lines.
+// Everything else goes back to its default, except for these, which belong
+// to the session rather than to the sound.
+pub const KEPT: [Param; 4] = [
+    Param::Oversampling,
+    Param::ClearOnStop,
+    Param::Panic,
+    Param::Seed,
+];
+
+pub struct Preset {
+    pub name: &'static str,
+    pub values: [f32; 8],
+    pub engine: Engine,
+}
+
+pub const FACTORY_PRESETS: [Preset; 9] = [
+    Preset {
+        name: "Init",
+        values: [0.25, 0.0, 0.0, 0.8, 0.5, 0.0, 0.0, 0.5],
+        engine: Engine::Classic,
+    },
+    Preset {
+        name: "Warped Cassette",
+        values: [0.12, 0.7, 0.65, 0.62, 0.5, 0.3, 0.35, 0.45],
+        engine: Engine::Classic,
+    },
+    Preset {
+        // +12, +12
+        name: "Octave Shimmer Loop",
+        values: [0.35, 0.3, 0.1, 0.85, 0.885, 0.42, 0.1, 0.6],
+        engine: Engine::TriLine,
+    },
+    Preset {
+        name: "Dusty Ping",
+        values: [0.07, 0.15, 0.45, 0.7, 0.5, 0.35, 0.2, 0.4],
+        engine: Engine::Classic,
+    },
+    Preset {
+        // +7, +7
+        name: "Fifth Drift",
+        values: [0.2, 0.5, 0.3, 0.75, 0.654, 0.38, 0.15, 0.5],
+        engine: Engine::Classic,
+    },
+    Preset {
+        // -12, -12
+        name: "Sunken Reel",
+        values: [0.5, 0.6, 0.5, 0.55, 0.115, 0.45, 0.25, 0.55],
+        engine: Engine::Classic,
+    },
+    Preset {
+        name: "Broken Rotor",
+        values: [0.03, 1.0, 1.0, 0.6, 0.5, 0.2, 0.5, 0.5],
+        engine: Engine::Classic,
+    },
+    Preset {
+        // +7, -12
+        name: "Split Horizon",
+        values: [0.28, 0.25, 0.2, 0.8, 0.038, 0.4, 0.1, 0.5],
+        engine: Engine::TriLine,
+    },
+    Preset {
+        // feedback is past the self-oscillation point on purpose
+        name: "Runaway Tape",
+        values: [0.18, 0.4, 0.35, 0.7, 0.5, 0.55, 0.6, 0.6],
+        engine: Engine::Classic,
+    },
+];
@@ -2,6 +2,8 @@
 use vst::buffer::AudioBuffer;
 use vst::plugin::{Category, Info, Plugin, PluginParameters};
 use vst::util::AtomicFloat;
+use vst::api::TimeInfoFlags;
+use vst::host::Host;
 
 // my own libs
 use dsp_lab::core::delay::{DelayLine, MixMethod, InterpMethod};
@@ -22,82 +24,296 @@ use std::io::prelude::*;
 use std::path::Path;
 
 // internal dependencies
-use super::Effect;
+use super::{Effect, EffectParameters};
+use crate::midi::{self, MidiAction};
+use crate::sync::{self, SyncMode};
+use crate::algo::{self, Engine, WrapMode, StereoMode, DuckSource};
+use crate::pitch::{self, PitchMode};
+use crate::params::{Param, PARAMS};
+use crate::ftz;
+use crate::oversample::{self, Oversampling};
 
 // === globals ===
+// stereo bleed of the classic engine, kept as it always was for old sessions,
+// the width control comes on top of it
 const WIDTH: f64 = 0.8;
 
+// Denormals are flushed to zero by `FtzGuard` where the platform allows it,
+// otherwise they are kept away by dithering the input and feedback paths. The
+// `denormal-dither` feature forces the dithering on everywhere.
+const DITHER: bool = cfg!(feature = "denormal-dither") || !ftz::SUPPORTED;
 
-pub fn process_chunk(parent: &mut Effect, buffer: &mut AudioBuffer<f32>) {
+// level of the tape hiss at full noise and age, before the band pass
+const HISS_GAIN: f64 = 0.1;
+
+// level of the ducking key (-20 dB) at which the ducking is as deep as it goes
+const DUCK_FULL: f64 = 0.1;
+
+// how long the wet signal takes to come back after a reset, in ms
+const RECOVERY_FADE: f64 = 50.0;
+
+// anything quieter than this (-120 dB) counts as silence
+const SILENCE: f64 = 1e-6;
+
+// time over which the silence detector averages the energy, in ms
+const QUIET_TIME: f64 = 10.0;
+
+// VST 2 has no way to say "forever", this is as long as a host will take
+const INFINITE_TAIL: isize = i32::MAX as isize;
+
+
+// parameter values scaled for the DSP, before smoothing
+struct RawParams {
+    time: f64,
+    vibe: f64,
+    age: f64,
+    fb: f64,
+    tone: f64,
+    shift_l: f64,
+    shift_r: f64,
+    sat: f64,
+    wet: f64,
+    freeze: f64,
+    wrap: WrapMode,
+    engine: Engine,
+    oversampling: Oversampling,
+    noise: f64,
+    scrape: bool,
+    width: f64,
+    stereo_mode: StereoMode,
+    duck: f64,
+    duck_attack: f64,
+    duck_release: f64,
+    duck_source: DuckSource,
+    // advanced page overrides, `None` follows the macro
+    flutter: Option<f64>,
+    drop_amt: Option<f64>,
+    wow_c: Option<f64>,
+    wow_s: Option<f64>,
+}
+
+impl RawParams {
     // === get parameters === parameter scaling ===
-    let time_raw = parent.params.dict.get(&0).unwrap().get() as f64 * 4450.0 + 50.0;
-    let vibe_raw = parent.params.dict.get(&1).unwrap().get() as f64;
-    let age_raw = parent.params.dict.get(&2).unwrap().get()  as f64;
-    let fb_raw = parent.params.dict.get(&5).unwrap().get() as f64 * 2.0;
-    let tone_raw = parent.params.dict.get(&3).unwrap().get() as f64;
-    let pitch_mode_raw = (parent.params.dict.get(&4).unwrap().get() * 130.0).round() as u32;
-    let sat_raw = parent.params.dict.get(&6).unwrap().get() as f64 * 6.0 + 0.125;
-    let wet_raw = parent.params.dict.get(&7).unwrap().get() as f64;
-
-    // === prepare to process chunk ===
-    // TODO: enable flush-to-zero and remove all the TINY stuff
+    // NOTE: ranges and tapers come from `PARAMS`, see `params.rs`
+    fn read(params: &EffectParameters, tempo: f64) -> Self {
+        let sync_mode = SyncMode::from_param(params.get(Param::Sync));
+        let pitch_mode = PitchMode::from_param(params.get(Param::PitchMode));
+        let (shift_l, shift_r) = match pitch_mode {
+            PitchMode::Classic => pitch::classic_shifts(params.get(Param::Pitch)),
+            _ => (
+                pitch::shift(pitch::snap(params.scaled(Param::PitchL), pitch_mode)),
+                pitch::shift(pitch::snap(params.scaled(Param::PitchR), pitch_mode)),
+            ),
+        };
+        let time = &PARAMS[Param::Time as usize];
+        Self {
+            time: sync::quantize(params.scaled(Param::Time), tempo, sync_mode, time.min, time.max),
+            vibe: params.scaled(Param::Vibe),
+            age: params.scaled(Param::Age),
+            fb: params.scaled(Param::Feedback),
+            tone: params.scaled(Param::Tone),
+            shift_l: shift_l,
+            shift_r: shift_r,
+            sat: params.scaled(Param::Distortion),
+            wet: params.scaled(Param::Moisture) / 100.0,
+            freeze: params.scaled(Param::Freeze),
+            wrap: WrapMode::from_param(params.get(Param::Wrap)),
+            engine: Engine::from_param(params.get(Param::Engine)),
+            oversampling: Oversampling::from_param(params.get(Param::Oversampling)),
+            noise: params.scaled(Param::Noise) / 100.0,
+            scrape: params.get(Param::Scrape) >= 0.5,
+            width: params.scaled(Param::Width) / 100.0,
+            stereo_mode: StereoMode::from_param(params.get(Param::StereoMode)),
+            duck: params.scaled(Param::Duck) / 100.0,
+            duck_attack: params.scaled(Param::DuckAttack),
+            duck_release: params.scaled(Param::DuckRelease),
+            duck_source: DuckSource::from_param(params.get(Param::DuckSource)),
+            flutter: params.micro(Param::Flutter),
+            drop_amt: params.micro(Param::Dropouts),
+            wow_c: params.micro(Param::WowCenter),
+            wow_s: params.micro(Param::WowSide),
+        }
+    }
+}
 
-    // === process chunk ===
+
+pub fn process_chunk(parent: &mut Effect, buffer: &mut AudioBuffer<f32>) {
+    // === host tempo and transport ===
+    // the last known tempo is kept if the host doesn't provide one
+    let mut transport_changed = false;
+    if let Some(host) = &parent.host {
+        if let Some(info) = host.get_time_info(TimeInfoFlags::TEMPO_VALID.bits()) {
+            if info.flags & TimeInfoFlags::TEMPO_VALID.bits() != 0 && info.tempo > 0.0 {
+                parent.tempo = info.tempo;
+            }
+            let playing = info.flags & TimeInfoFlags::TRANSPORT_PLAYING.bits() != 0;
+            transport_changed = playing != parent.playing;
+            parent.playing = playing;
+        }
+    }
+    if transport_changed && parent.params.get(Param::ClearOnStop) >= 0.5 {
+        parent.reset_dsp();
+    }
+
+    let mut raw = RawParams::read(&parent.params, parent.tempo);
+    let mut next_event = 0;
+    let mut next_change = 0;
+    let samples = buffer.samples();
+
+    let fade_step = 1000.0 / (RECOVERY_FADE * parent.sr);
+    let quiet_coef = 1.0 - (-1000.0 / (QUIET_TIME * parent.sr)).exp();
+    let mut blown = false;
+
+    // === channel layout ===
+    // NOTE: hosts don't always hand over all the channels declared in `Info`.
+    // A mono input is fed to both sides, and a mono output gets the sum of
+    // both, so that the heads are still spread apart in between. The second
+    // pair of inputs is the sidechain, outputs past the first two are
+    // silenced, and a channel shorter than the block is read as silence past
+    // its end.
     let (inputs, outputs) = buffer.split();
+    let mono_out = outputs.len() == 1;
+    let in_l: &[f32] = if inputs.len() > 0 {inputs.get(0)} else {&[]};
+    let in_r: &[f32] = if inputs.len() > 1 {inputs.get(1)} else {in_l};
+    let sc_l: &[f32] = if inputs.len() > 2 {inputs.get(2)} else {&[]};
+    let sc_r: &[f32] = if inputs.len() > 3 {inputs.get(3)} else {sc_l};
+    let split = outputs.len().min(1);
+    let (mut outs_l, mut outs_r) = outputs.split_at_mut(split);
+    for i in 1..outs_r.len() {
+        for s in outs_r.get_mut(i).iter_mut() {
+            *s = 0.0;
+        }
+    }
+    let out_l: &mut [f32] = if outs_l.len() > 0 {outs_l.get_mut(0)} else {&mut []};
+    let out_r: &mut [f32] = if outs_r.len() > 0 {outs_r.get_mut(0)} else {&mut []};
+
+    // === idle block ===
+    // NOTE: a block in which the tape chain would be skipped on every sample,
+    // see the silence detection below, only steps what runs freely, the same way the sample
+    // loop does, so the outcome doesn't depend on the block size. The seed
+    // and panic are checked as they would be on the first sample, the loop
+    // below finds them already handled if the block isn't idle after all.
+    let block_events = parent.midi_events.iter().any(|e| e.frame < samples)
+                    || parent.param_changes.iter().any(|c| c.frame < samples);
+    if !block_events && parent.quiet_samples >= parent.dly_l.len() {
+        if parent.params.seed() != parent.seed {
+            parent.build_random_sources();
+        }
+        let panic_held = parent.params.get(Param::Panic) >= 0.5;
+        if panic_held && !parent.panic_held {
+            parent.reset_dsp();
+        }
+        parent.panic_held = panic_held;
+
+        let mut energy = parent.energy;
+        let idle = (0..samples).all(|i| {
+            let power = input_power(in_l.get(i).copied(), in_r.get(i).copied());
+            energy = track_energy(energy, power, quiet_coef);
+            energy < SILENCE * SILENCE
+        });
+        if idle {
+            parent.energy = energy;
+            parent.quiet_samples = parent.quiet_samples.saturating_add(samples);
+            for _ in 0..samples {
+                idle_step(parent, &raw, fade_step);
+            }
+            for s in out_l.iter_mut().chain(out_r.iter_mut()) {
+                *s = 0.0;
+            }
+            end_block(parent, 0, samples);
+            return;
+        }
+    }
+
+    // === process chunk ===
+    for i in 0..samples {
+        let left_in  = in_l.get(i).copied().unwrap_or(0.0);
+        let right_in = in_r.get(i).copied().unwrap_or(0.0);
 
-    // Iterate over inputs as (&f32, &f32)
-    let (l, r) = inputs.split_at(1);
-    let stereo_in = l[0].iter().zip(r[0].iter());
+        // === MIDI events ===
+        // NOTE: events are expected in order, as the VST spec requires.
+        let mut reset_heads = false;
+        let mut panic = false;
+        while next_event < parent.midi_events.len() && parent.midi_events[next_event].frame <= i {
+            match midi::parse(parent.midi_events[next_event].data) {
+                MidiAction::Param(index, val) => parent.params.set_parameter(index, val),
+                MidiAction::Program(preset) => parent.params.change_preset(preset),
+                MidiAction::ResetHeads => reset_heads = true,
+                MidiAction::Panic => panic = true,
+                MidiAction::None => (),
+            }
+            raw = RawParams::read(&parent.params, parent.tempo);
+            next_event += 1;
+        }
+
+        // === queued parameter changes ===
+        while next_change < parent.param_changes.len() && parent.param_changes[next_change].frame <= i {
+            let change = parent.param_changes[next_change];
+            parent.params.set_parameter(change.index, change.value);
+            raw = RawParams::read(&parent.params, parent.tempo);
+            next_change += 1;
+        }
 
-    // Iterate over outputs as (&mut f32, &mut f32)
-    let (mut l, mut r) = outputs.split_at_mut(1);
-    let stereo_out = l[0].iter_mut().zip(r[0].iter_mut());
+        // === seed ===
+        // a new seed rerolls the dropouts, noise and LFO phases straight away
+        if parent.params.seed() != parent.seed {
+            parent.build_random_sources();
+        }
 
-    for ((left_in, right_in), (left_out, right_out)) in stereo_in.zip(stereo_out) {
+        // === panic ===
+        // the panic control fires when it's switched on, not while it stays on
+        let panic_held = parent.params.get(Param::Panic) >= 0.5;
+        if panic || (panic_held && !parent.panic_held) {
+            parent.reset_dsp();
+        }
+        parent.panic_held = panic_held;
+
+        // === silence detection ===
+        // NOTE: once the energy of the input and of everything written onto
+        // the tape has stayed below silence over the whole length of the tape,
+        // no head can read anything audible back and the loop can't ring by
+        // itself anymore. As long as the input stays silent too, the tape
+        // chain is skipped, and only what runs freely is stepped, see
+        // `idle_step`, so that it picks up where it would have been.
+        let power = input_power(in_l.get(i).copied(), in_r.get(i).copied());
+        let idle_energy = track_energy(parent.energy, power, quiet_coef);
+        if parent.quiet_samples >= parent.dly_l.len() && idle_energy < SILENCE * SILENCE {
+            parent.energy = idle_energy;
+            parent.quiet_samples = parent.quiet_samples.saturating_add(1);
+            idle_step(parent, &raw, fade_step);
+            if let Some(s) = out_l.get_mut(i) {*s = 0.0;}
+            if let Some(s) = out_r.get_mut(i) {*s = 0.0;}
+            continue;
+        }
 
         // === parameter filtering ===
-        let time = parent.param_1_lp.step(time_raw);
-        let vibe = parent.param_2_lp.step(vibe_raw);
-        let age  = parent.param_3_lp.step(age_raw);
-        let fb   = parent.param_4_lp.step(fb_raw);
-        let tone = parent.param_5_lp.step(tone_raw * tone_raw * tone_raw * tone_raw);
-        let (shift_l, shift_r): (f64, f64) = match pitch_mode_raw {
-            0..=9     => ( 0.5,  -0.5),
-            10..=19   => (-0.5,  -0.5),
-            20..=29   => (-0.25, -0.5),
-            30..=39   => ( 1.0,  -0.5),
-            40..=49   => (-0.25, -0.25),
-            50..=59   => ( 0.0,  -0.25),
-            60..=69   => ( 0.0,   0.0),
-            70..=79   => ( 0.5,   0.0),
-            80..=89   => ( 0.5,   0.5),
-            90..=99   => ( 0.5,  -0.25),
-            100..=109 => ( 1.0,   0.5),
-            110..=119 => ( 1.0,   1.0),
-            _         => ( 1.0,  -0.25)
-        };
-        let sat = parent.param_6_lp.step(sat_raw);
-        let wet = parent.param_7_lp.step(wet_raw);
+        let time = parent.smooth(Param::Time, raw.time);
+        let vibe = parent.smooth(Param::Vibe, raw.vibe);
+        let age  = parent.smooth(Param::Age, raw.age);
+        let fb   = parent.smooth(Param::Feedback, raw.fb);
+        let tone = parent.smooth(Param::Tone, raw.tone);
+        let (shift_l, shift_r) = (raw.shift_l, raw.shift_r);
+        let sat = parent.smooth(Param::Distortion, raw.sat);
+        let wet = parent.smooth(Param::Moisture, raw.wet);
+        let frz = parent.smooth(Param::Freeze, raw.freeze);
+        let os = raw.oversampling;
+        let noise = parent.smooth(Param::Noise, raw.noise);
+        let width = parent.smooth(Param::Width, raw.width);
+        let duck  = parent.smooth(Param::Duck, raw.duck);
+
+        track_frozen(parent, &raw, time);
 
         // === macro mappings ===
         // NOTE: parameters on the UI are macros for a larger set of hidden
-        // parameters
+        // parameters, each of which can be overridden on the advanced page
         let dry      = 1.0 - wet;
-        let flutter  = age * age;
-        let drop_amt = age;
+        let flutter  = parent.micro(Param::Flutter, raw.flutter, age * age);
+        let drop_amt = parent.micro(Param::Dropouts, raw.drop_amt, age);
+        let wow_c    = parent.micro(Param::WowCenter, raw.wow_c, vibe * 8.0);
+        let wow_s    = parent.micro(Param::WowSide, raw.wow_s, vibe * 10.0);
         let squareness   = 0.8 - sat * 0.8;
         let coercitivity = sat * 0.1;
 
-        // NOTE: what's the deal with all the "TINY"? That's anywhere that
-        // a calculation has a potential to give a denormal number as a result.
-        // I won't be doing this paranoid treatment on everything, simply
-        // because all audio input is passing through a denormal filter
-        // anyway, but parameters are not passed through a denormal filter,
-        // so they need to be addressed, especially because they are being
-        // processed by an IIR filter, which produces denormals as the impulse
-        // response approaches zero asymptotically.
-        // TODO: HACK: the proper way to deal with denormals is fulsh-to-zero
-
         // === micro-parameter mappings ===
         // NOTE: a micro-parameter is a parameter passed to low-level processes
 
@@ -112,88 +328,39 @@ pub fn process_chunk(parent: &mut Effect, buffer: &mut AudioBuffer<f32>) {
         let lfo_2 = parent.lfo_2.step();
         let lfo_3 = parent.lfo_3.step();
         let lfo_4 = parent.lfo_4.step();
+        let lfo_5 = parent.lfo_5.step();
 
-        // if pitch shifting is enabled, move read indexes dynamically
-        if shift_l != 0.0 || shift_r != 0.0 {
-            parent.left_pos -= shift_l / parent.sr * 1000.0;
-            if parent.left_pos < 0.0 {
-                parent.left_pos += time * consts::LOG2_E;
-            }
-            if parent.left_pos > time * consts::LOG2_E {
-                parent.left_pos -= time * consts::LOG2_E;
-            }
-            parent.right_pos -= shift_r / parent.sr * 1000.0;
-            if parent.right_pos < 0.0{
-                parent.right_pos += time * consts::LOG2_E * consts::LOG2_E;
-            }
-            if parent.right_pos > time * consts::LOG2_E * consts::LOG2_E {
-                parent.right_pos -= time * consts::LOG2_E * consts::LOG2_E;
-            }
-        } else {
-            parent.left_pos = time * consts::LOG2_E;
-            parent.right_pos = time * consts::LOG2_E * consts::LOG2_E;
-        }
-
-        // flutter
-        let tri_1 = parent.flut_tri_1.step();
-        let tri_2 = parent.flut_tri_2.step();
-        let tri_3 = parent.flut_tri_3.step();
-        let tri_4 = parent.flut_tri_4.step();
-        let tri_5 = parent.flut_tri_5.step();
-        let spike_flut = (tri_1 * tri_1 * tri_1 * tri_1 * 0.1
-                       + tri_2 * tri_2 * tri_2 * tri_2 * tri_2 * tri_2 * 0.325
-                       + tri_3 * tri_3 * tri_3 * tri_3 * tri_3 * tri_3 * tri_3 * tri_3 * 0.55
-                       + tri_4 * tri_4 * tri_4 * tri_4 * tri_4 * tri_4 * tri_4 * tri_4 * tri_4 * tri_4 * tri_4 * tri_4 * 0.775
-                       + tri_5 * tri_5 * tri_5 * tri_5 * tri_5 * tri_5 * tri_5 * tri_5 * tri_5 * tri_5 * tri_5 * tri_5 * tri_5 * tri_5 * tri_5 * tri_5)
-                       * 10.0;
-        let sin_1 = parent.flut_sin_1.step();
-        let sin_2 = parent.flut_sin_2.step();
-        let rotor_flut = (sin_1 * sin_1 * sin_1 * sin_1
-                       +  sin_2 * sin_2 * sin_2 * sin_2)
-                       * 0.333;
-        let scrape_flut = parent.flut_scrape.step().abs() * 0.025;
-        let total_flut = spike_flut + rotor_flut /* + scrape_flut */;
-
-        let dly_l = &mut parent.dly_l;
-        let dly_r = &mut parent.dly_r;
-        dly_l.set_offset(0, time           + vibe * lfo_1 * lfo_1 * lfo_1 * lfo_1 *  8.0 + total_flut * flutter);
-        dly_l.set_offset(1, parent.left_pos  + vibe * lfo_2 * lfo_2 * lfo_2 * lfo_2 * 10.0 + total_flut * flutter);
-        dly_r.set_offset(0, time           + vibe * lfo_3 * lfo_3 * lfo_3 * lfo_3 *  8.0 + total_flut * flutter);
-        dly_r.set_offset(1, parent.right_pos + vibe * lfo_4 * lfo_4 * lfo_4 * lfo_4 * 10.0 + total_flut * flutter);
-
-        let drop_l = &mut parent.drop_l;
-        let drop_r = &mut parent.drop_r;
+        // a side that isn't shifted rests at the end of its span, where the
+        // other wrap modes would fade it out
+        move_heads(parent, &raw, time, reset_heads);
+        let wrap_l = if shift_l != 0.0 {raw.wrap} else {WrapMode::Hard};
+        let wrap_r = if shift_r != 0.0 {raw.wrap} else {WrapMode::Hard};
+
+        let total_flut = flutter_step(parent, raw.scrape);
+
+        // each side head is played by two read heads, see `algo::wrap_heads`
+        let mod_c_l = wow_c * lfo_1 * lfo_1 * lfo_1 * lfo_1 + total_flut * flutter;
+        let mod_s_l = wow_s * lfo_2 * lfo_2 * lfo_2 * lfo_2 + total_flut * flutter;
+        let mod_c_r = wow_c * lfo_3 * lfo_3 * lfo_3 * lfo_3 + total_flut * flutter;
+        let mod_s_r = wow_s * lfo_4 * lfo_4 * lfo_4 * lfo_4 + total_flut * flutter;
+        let ((pos_a_l, gain_a_l), (pos_b_l, gain_b_l)) = algo::wrap_heads(parent.left_pos, time * consts::LOG2_E, wrap_l);
+        let ((pos_a_r, gain_a_r), (pos_b_r, gain_b_r)) = algo::wrap_heads(parent.right_pos, time * consts::LOG2_E * consts::LOG2_E, wrap_r);
+
+        // NOTE: the oversampled saturation delays what goes onto the tape, and
+        // the oversampled clipper delays the feedback on top of that, so the
+        // heads read earlier by both to keep the repeats exactly `time` apart.
+        // The first repeat is then early by one latency, which is made up for
+        // at the output.
+        let os_comp = (2 * os.latency()) as f64 / parent.sr * 1000.0;
+
+        let drop_gain_l = parent.drop_l.next_gain();
+        let drop_gain_r = parent.drop_r.next_gain();
 
         let tone_lp_l = &mut parent.tone_lp_l;
         let tone_lp_r = &mut parent.tone_lp_r;
-        tone_lp_l.set_cutoff(tone * 18000.0);
-        tone_lp_r.set_cutoff(tone * 18000.0);
-    
-        // === inputs pre-processing ===
-        let mut l = parent.in_dith_l.step(*left_in  as f64);
-        let mut r = parent.in_dith_r.step(*right_in as f64);
-        let dry_l = l;
-        let dry_r = r;
-        l += parent.fb_l;
-        r += parent.fb_r;
-
-        // === main chain ===
-        l = fast_sigmoid(l * sat) / (sat);
-        r = fast_sigmoid(r * sat) / (sat);
-        //l = x_fade(l, tone, combs_l.step(l));
-        //r = x_fade(r, tone, combs_r.step(r));
-        l = x_fade(l, drop_amt, drop_l.step(l));
-        r = x_fade(r, drop_amt, drop_r.step(r));
-        l = chain!(l => dly_l => tone_lp_l);
-        r = chain!(r => dly_r => tone_lp_r);
-        l = l * WIDTH + r * (1.0 - WIDTH);
-        r = r * WIDTH + l * (1.0 - WIDTH);
-
-        // === output ===
-        *left_out  = (l * wet + dry_l * dry) as f32;
-        *right_out = (r * wet + dry_r * dry) as f32;
+        tone_lp_l.set_cutoff(tone);
+        tone_lp_r.set_cutoff(tone);
 
-        // === feedback chain ===
         let fb_dith_l = &mut parent.fb_dith_l;
         let fb_dith_r = &mut parent.fb_dith_r;
         let block_dc_l = &mut parent.block_dc_l;
@@ -204,10 +371,637 @@ pub fn process_chunk(parent: &mut Effect, buffer: &mut AudioBuffer<f32>) {
         let fb_antialias_r_2 = &mut parent.fb_antialias_r_2;
         let fb_antialias_l_3 = &mut parent.fb_antialias_l_3;
         let fb_antialias_r_3 = &mut parent.fb_antialias_r_3;
-        parent.fb_l = chain!(var_clip(l * fb, 0.8) => block_dc_l => fb_antialias_l_1 => fb_antialias_l_2 => fb_antialias_l_3 => fb_dith_l);
-        parent.fb_r = chain!(var_clip(r * fb, 0.8) => block_dc_r => fb_antialias_r_1 => fb_antialias_r_2 => fb_antialias_r_3 => fb_dith_r);
+    
+        // === inputs pre-processing ===
+        // a NaN or Inf from the host is not let into the loop
+        let mut l = dither(&mut parent.in_dith_l, if left_in.is_finite()  {left_in  as f64} else {0.0});
+        let mut r = dither(&mut parent.in_dith_r, if right_in.is_finite() {right_in as f64} else {0.0});
+        let dry_l = l;
+        let dry_r = r;
+        let written;    // loudest sample written onto the tape
+
+        // the ducking listens to the input when no sidechain is connected
+        let key = match raw.duck_source {
+            DuckSource::Sidechain if !sc_l.is_empty() => {
+                let level = |ch: &[f32]| ch.get(i).copied().unwrap_or(0.0).abs() as f64;
+                let key = level(sc_l).max(level(sc_r));
+                if key.is_finite() {key} else {0.0}
+            },
+            _ => dry_l.abs().max(dry_r.abs()),
+        };
+
+        // in M/S mode the left chain records the mid and the right one the side
+        if raw.stereo_mode == StereoMode::MidSide {
+            let (m, s) = ((l + r) * 0.5, (l - r) * 0.5);
+            l = m;
+            r = s;
+        }
+
+        if raw.engine == Engine::Classic {
+            // when frozen, nothing new is recorded onto the tape
+            l = l * (1.0 - frz) + parent.fb_l;
+            r = r * (1.0 - frz) + parent.fb_r;
+
+            // === main chain ===
+            // NOTE: when frozen, saturation and dropouts are bypassed on the way
+            // into the tape, so that the loop doesn't degrade. The dropouts move
+            // over the playback instead.
+            l = x_fade(parent.os_sat_l.process(l, os, |x| fast_sigmoid(x * sat) / sat), frz, l);
+            r = x_fade(parent.os_sat_r.process(r, os, |x| fast_sigmoid(x * sat) / sat), frz, r);
+            //l = x_fade(l, tone, combs_l.step(l));
+            //r = x_fade(r, tone, combs_r.step(r));
+            l = x_fade(l, drop_amt * (1.0 - frz), l * drop_gain_l);
+            r = x_fade(r, drop_amt * (1.0 - frz), r * drop_gain_r);
+            parent.dly_l.write(l);
+            parent.dly_r.write(r);
+            written = l.abs().max(r.abs());
+            let side_l = parent.dly_l.read(pos_a_l + mod_s_l - os_comp) * gain_a_l + parent.dly_l.read(pos_b_l + mod_s_l - os_comp) * gain_b_l;
+            let side_r = parent.dly_r.read(pos_a_r + mod_s_r - os_comp) * gain_a_r + parent.dly_r.read(pos_b_r + mod_s_r - os_comp) * gain_b_r;
+            l = (parent.dly_l.read(time + mod_c_l - os_comp) + side_l) * 0.5;
+            r = (parent.dly_r.read(time + mod_c_r - os_comp) + side_r) * 0.5;
+            l = tone_lp_l.step(l);
+            r = tone_lp_r.step(r);
+            // in M/S mode the bleed would leak the side into the mid
+            if raw.stereo_mode == StereoMode::LeftRight {
+                l = l * WIDTH + r * (1.0 - WIDTH);
+                r = r * WIDTH + l * (1.0 - WIDTH);
+            }
+            l = x_fade(l, drop_amt * frz, l * drop_gain_l);
+            r = x_fade(r, drop_amt * frz, r * drop_gain_r);
+
+            // === feedback chain ===
+            // NOTE: when frozen, the loop is fed by an unmodulated center head at
+            // unity gain, on a whole sample, around the clipper, DC blocker,
+            // antialiasing and dithering, so the tape plays forever without
+            // losing anything while wow, flutter and the pitch heads keep
+            // moving over it.
+            let clip_l = parent.os_clip_l.process(l * fb, os, |x| var_clip(x, 0.8));
+            let clip_r = parent.os_clip_r.process(r * fb, os, |x| var_clip(x, 0.8));
+            let loop_l = block_dc_l.step(clip_l);
+            let loop_r = block_dc_r.step(clip_r);
+            let filt_l = chain!(loop_l => fb_antialias_l_1 => fb_antialias_l_2 => fb_antialias_l_3);
+            let filt_r = chain!(loop_r => fb_antialias_r_1 => fb_antialias_r_2 => fb_antialias_r_3);
+            parent.fb_l = x_fade(dither(fb_dith_l, filt_l), frz, parent.dly_l.read_exact(parent.frozen_c));
+            parent.fb_r = x_fade(dither(fb_dith_r, filt_r), frz, parent.dly_r.read_exact(parent.frozen_c));
+        } else {
+            // NOTE: the center line is fed with the mid signal and panned
+            // center, the side lines only have their pitch shifting heads and
+            // are panned hard left and right. Each line feeds back onto itself.
+            // In M/S mode the left line already is the mid, and the right line
+            // the side.
+            let drop_gain_c = parent.drop_c.next_gain();
+            let mod_c = wow_c * lfo_5 * lfo_5 * lfo_5 * lfo_5 + total_flut * flutter;
+            let tone_lp_c = &mut parent.tone_lp_c;
+            tone_lp_c.set_cutoff(tone);
+
+            let mid = if raw.stereo_mode == StereoMode::MidSide {l} else {(l + r) * 0.5};
+            let mut c = mid * (1.0 - frz) + parent.fb_c;
+            l = l * (1.0 - frz) + parent.fb_l;
+            r = r * (1.0 - frz) + parent.fb_r;
+
+            // === main chain ===
+            c = x_fade(parent.os_sat_c.process(c, os, |x| fast_sigmoid(x * sat) / sat), frz, c);
+            l = x_fade(parent.os_sat_l.process(l, os, |x| fast_sigmoid(x * sat) / sat), frz, l);
+            r = x_fade(parent.os_sat_r.process(r, os, |x| fast_sigmoid(x * sat) / sat), frz, r);
+            c = x_fade(c, drop_amt * (1.0 - frz), c * drop_gain_c);
+            l = x_fade(l, drop_amt * (1.0 - frz), l * drop_gain_l);
+            r = x_fade(r, drop_amt * (1.0 - frz), r * drop_gain_r);
+            parent.dly_c.write(c);
+            parent.dly_l.write(l);
+            parent.dly_r.write(r);
+            written = c.abs().max(l.abs()).max(r.abs());
+            c = parent.dly_c.read(time + mod_c - os_comp);
+            l = parent.dly_l.read(pos_a_l + mod_s_l - os_comp) * gain_a_l + parent.dly_l.read(pos_b_l + mod_s_l - os_comp) * gain_b_l;
+            r = parent.dly_r.read(pos_a_r + mod_s_r - os_comp) * gain_a_r + parent.dly_r.read(pos_b_r + mod_s_r - os_comp) * gain_b_r;
+            c = tone_lp_c.step(c);
+            l = tone_lp_l.step(l);
+            r = tone_lp_r.step(r);
+            c = x_fade(c, drop_amt * frz, c * drop_gain_c);
+            l = x_fade(l, drop_amt * frz, l * drop_gain_l);
+            r = x_fade(r, drop_amt * frz, r * drop_gain_r);
+
+            // === feedback chain ===
+            // NOTE: same as the classic engine, each frozen line loops over
+            // its own resting length.
+            let block_dc_c = &mut parent.block_dc_c;
+            let fb_antialias_c_1 = &mut parent.fb_antialias_c_1;
+            let fb_antialias_c_2 = &mut parent.fb_antialias_c_2;
+            let fb_antialias_c_3 = &mut parent.fb_antialias_c_3;
+            let clip_c = parent.os_clip_c.process(c * fb, os, |x| var_clip(x, 0.8));
+            let clip_l = parent.os_clip_l.process(l * fb, os, |x| var_clip(x, 0.8));
+            let clip_r = parent.os_clip_r.process(r * fb, os, |x| var_clip(x, 0.8));
+            let loop_c = block_dc_c.step(clip_c);
+            let loop_l = block_dc_l.step(clip_l);
+            let loop_r = block_dc_r.step(clip_r);
+            let filt_c = chain!(loop_c => fb_antialias_c_1 => fb_antialias_c_2 => fb_antialias_c_3);
+            let filt_l = chain!(loop_l => fb_antialias_l_1 => fb_antialias_l_2 => fb_antialias_l_3);
+            let filt_r = chain!(loop_r => fb_antialias_r_1 => fb_antialias_r_2 => fb_antialias_r_3);
+            parent.fb_c = x_fade(dither(&mut parent.fb_dith_c, filt_c), frz, parent.dly_c.read_exact(parent.frozen_c));
+            parent.fb_l = x_fade(dither(fb_dith_l, filt_l), frz, parent.dly_l.read_exact(parent.frozen_l));
+            parent.fb_r = x_fade(dither(fb_dith_r, filt_r), frz, parent.dly_r.read_exact(parent.frozen_r));
+
+            // === stereo mix ===
+            // the center goes to both sides, which in M/S mode is the mid
+            if raw.stereo_mode == StereoMode::MidSide {
+                l = (c + l) * 0.5;
+                r = r * 0.5;
+            } else {
+                l = (c + l) * 0.5;
+                r = (c + r) * 0.5;
+            }
+        }
+
+        // === stereo width ===
+        if raw.stereo_mode == StereoMode::MidSide {
+            let (m, s) = (l, r);
+            l = m + s;
+            r = m - s;
+        }
+        let (mid, side) = ((l + r) * 0.5, (l - r) * 0.5 * width);
+        l = mid + side;
+        r = mid - side;
+
+        // === tape noise ===
+        // NOTE: hiss is added to what comes off the tape, but not recorded
+        // back onto it, so it doesn't build up with the feedback. Modulation
+        // noise is the signal's own grain, it gets rougher as the flutter
+        // shakes the tape. Both stop with the tape chain once it has rung
+        // out, see `idle_step`.
+        let hiss_gain = noise * noise * HISS_GAIN * (0.25 + 0.75 * age);
+        let hiss_l = parent.hiss_lp_l.step(parent.hiss_l.step());
+        let hiss_r = parent.hiss_lp_r.step(parent.hiss_r.step());
+        let hiss_l = hiss_l - parent.hiss_low_l.step(hiss_l);
+        let hiss_r = hiss_r - parent.hiss_low_r.step(hiss_r);
+        let mod_depth = noise * (total_flut * flutter * 0.02).min(0.5);
+        l = l * (1.0 + parent.mod_noise_l.step() * mod_depth) + hiss_l * hiss_gain;
+        r = r * (1.0 + parent.mod_noise_r.step() * mod_depth) + hiss_r * hiss_gain;
+
+        // === latency compensation ===
+        // the dry signal is delayed by the latency reported to the host, the
+        // wet one also by what the heads read early, see `os_comp`
+        let dry_l = parent.dry_comp_l.step(dry_l, oversample::MAX_LATENCY);
+        let dry_r = parent.dry_comp_r.step(dry_r, oversample::MAX_LATENCY);
+        l = parent.wet_comp_l.step(l, oversample::MAX_LATENCY + os.latency());
+        r = parent.wet_comp_r.step(r, oversample::MAX_LATENCY + os.latency());
+
+        // === ducking ===
+        // NOTE: the key isn't delayed like the dry signal, which gives the
+        // ducking a little lookahead.
+        let env = parent.duck_env.step(key, raw.duck_attack, raw.duck_release);
+        let duck_gain = 1.0 - duck * (env / DUCK_FULL).min(1.0);
+        l *= duck_gain;
+        r *= duck_gain;
+
+        parent.energy = track_energy(parent.energy, power + written * written, quiet_coef);
+        if parent.energy < SILENCE * SILENCE {
+            parent.quiet_samples = parent.quiet_samples.saturating_add(1);
+        } else {
+            parent.quiet_samples = 0;
+        }
+
+        // === non-finite guard ===
+        // NOTE: a NaN or Inf anywhere in the loop would stay on the tape and
+        // in the feedback forever, so the wet signal is muted for the rest of
+        // the block, and everything is reset after it.
+        if blown || !(l + r + parent.fb_l + parent.fb_r + parent.fb_c).is_finite() {
+            blown = true;
+            l = 0.0;
+            r = 0.0;
+        }
+        parent.fade_in = (parent.fade_in + fade_step).min(1.0);
+        l *= parent.fade_in;
+        r *= parent.fade_in;
+
+        // === output ===
+        let left  = l * wet + dry_l * dry;
+        let right = r * wet + dry_r * dry;
+        if mono_out {
+            if let Some(s) = out_l.get_mut(i) {*s = ((left + right) * 0.5) as f32;}
+        } else {
+            if let Some(s) = out_l.get_mut(i) {*s = left as f32;}
+            if let Some(s) = out_r.get_mut(i) {*s = right as f32;}
+        }
+    }
+
+    if blown {
+        parent.logger.log("process_chunk: non-finite value in the loop, resetting the tape, filters and feedback\n");
+        parent.reset_dsp();
+    }
+
+    end_block(parent, next_change, samples);
+}
+
+// bookkeeping of the events and queued changes at the end of a block
+fn end_block(parent: &mut Effect, applied_changes: usize, samples: usize) {
+    // events only apply to the block they were sent with
+    parent.midi_events.clear();
+
+    // queued changes past this block move on to the next one
+    parent.param_changes.drain(..applied_changes);
+    for change in parent.param_changes.iter_mut() {
+        change.frame = change.frame.saturating_sub(samples);
+    }
+}
+
+/// How long the wet signal takes to decay below `SILENCE` once the input
+/// stops, in samples. Infinite when frozen or when the feedback is loud enough
+/// to self-oscillate.
+pub fn tail_size(parent: &Effect) -> isize {
+    let raw = RawParams::read(&parent.params, parent.tempo);
+    if raw.freeze >= 0.5 || raw.fb >= 1.0 {
+        return INFINITE_TAIL;
     }
 
-    // === post-process cleanup ===
-    // TODO: after processing, restore previous value of flush-to-zero
-}
\ No newline at end of file
+    // NOTE: the loop loses at least `1 - fb` per trip around the tape, and
+    // the longest trip is the right head's, plus the deepest wow and flutter
+    // (about 40 ms).
+    let trip = (raw.time * consts::LOG2_E * consts::LOG2_E + 40.0) * 0.001 * parent.sr;
+    let trips = if raw.fb > 0.0 {(SILENCE.ln() / raw.fb.ln()).ceil() + 1.0} else {1.0};
+    let tail = trip * trips + oversample::MAX_LATENCY as f64;
+    return tail.min(INFINITE_TAIL as f64) as isize;
+}
+
+fn dither(dith: &mut DenormalDither, x: f64) -> f64 {
+    if DITHER {dith.step(x)} else {x}
+}
+
+// squared level of an input sample, NaN and Inf from the host count as silence
+fn input_power(left: Option<f32>, right: Option<f32>) -> f64 {
+    let power = |x: Option<f32>| match x {
+        Some(x) if x.is_finite() => (x as f64) * (x as f64),
+        _ => 0.0,
+    };
+    return power(left) + power(right);
+}
+
+// one step of the silence detector, the energy is flushed to zero far below
+// silence so that it doesn't decay into denormals
+fn track_energy(energy: f64, power: f64, coef: f64) -> f64 {
+    let energy = energy + (power - energy) * coef;
+    return if energy < SILENCE * SILENCE * 1e-6 {0.0} else {energy};
+}
+
+// the frozen loops are read a whole number of samples back, an interpolated
+// read would dull them a little more on every pass, and they keep the length
+// they had when the freeze came on
+fn track_frozen(parent: &mut Effect, raw: &RawParams, time: f64) {
+    if raw.freeze == 0.0 {
+        let sr = parent.sr;
+        let samples = |ms: f64| (ms * 0.001 * sr).round() as usize;
+        parent.frozen_c = samples(time);
+        parent.frozen_l = samples(time * consts::LOG2_E);
+        parent.frozen_r = samples(time * consts::LOG2_E * consts::LOG2_E);
+    }
+}
+
+// a note-on or a reset puts the read heads back where they start from, and
+// if pitch shifting is enabled, the read indexes move dynamically
+fn move_heads(parent: &mut Effect, raw: &RawParams, time: f64, reset: bool) {
+    let (shift_l, shift_r) = (raw.shift_l, raw.shift_r);
+    if reset || parent.heads_reset {
+        parent.left_pos = time * consts::LOG2_E;
+        parent.right_pos = time * consts::LOG2_E * consts::LOG2_E;
+        parent.heads_reset = false;
+    }
+
+    if shift_l != 0.0 || shift_r != 0.0 {
+        parent.left_pos -= shift_l / parent.sr * 1000.0;
+        if parent.left_pos < 0.0 {
+            parent.left_pos += time * consts::LOG2_E;
+        }
+        if parent.left_pos > time * consts::LOG2_E {
+            parent.left_pos -= time * consts::LOG2_E;
+        }
+        parent.right_pos -= shift_r / parent.sr * 1000.0;
+        if parent.right_pos < 0.0{
+            parent.right_pos += time * consts::LOG2_E * consts::LOG2_E;
+        }
+        if parent.right_pos > time * consts::LOG2_E * consts::LOG2_E {
+            parent.right_pos -= time * consts::LOG2_E * consts::LOG2_E;
+        }
+    } else {
+        parent.left_pos = time * consts::LOG2_E;
+        parent.right_pos = time * consts::LOG2_E * consts::LOG2_E;
+    }
+}
+
+// spikes from the capstan, rotation of the reels and scrape of the tape
+fn flutter_step(parent: &mut Effect, scrape: bool) -> f64 {
+    let tri_1 = parent.flut_tri_1.step();
+    let tri_2 = parent.flut_tri_2.step();
+    let tri_3 = parent.flut_tri_3.step();
+    let tri_4 = parent.flut_tri_4.step();
+    let tri_5 = parent.flut_tri_5.step();
+    let spike_flut = (tri_1 * tri_1 * tri_1 * tri_1 * 0.1
+                   + tri_2 * tri_2 * tri_2 * tri_2 * tri_2 * tri_2 * 0.325
+                   + tri_3 * tri_3 * tri_3 * tri_3 * tri_3 * tri_3 * tri_3 * tri_3 * 0.55
+                   + tri_4 * tri_4 * tri_4 * tri_4 * tri_4 * tri_4 * tri_4 * tri_4 * tri_4 * tri_4 * tri_4 * tri_4 * 0.775
+                   + tri_5 * tri_5 * tri_5 * tri_5 * tri_5 * tri_5 * tri_5 * tri_5 * tri_5 * tri_5 * tri_5 * tri_5 * tri_5 * tri_5 * tri_5 * tri_5)
+                   * 10.0;
+    let sin_1 = parent.flut_sin_1.step();
+    let sin_2 = parent.flut_sin_2.step();
+    let rotor_flut = (sin_1 * sin_1 * sin_1 * sin_1
+                   +  sin_2 * sin_2 * sin_2 * sin_2)
+                   * 0.333;
+    let scrape_flut = parent.flut_scrape.step().abs() * 0.025;
+    return spike_flut + rotor_flut + if scrape {scrape_flut} else {0.0};
+}
+
+// NOTE: while the tape chain is skipped, everything that runs freely is still
+// stepped as it would be in the sample loop: the parameter filters, LFOs, read
+// heads, flutter, dropouts and noise generators, and the fade after a reset.
+// The filters, compensation delays and ducking envelope only see silence then,
+// and are left as they are.
+fn idle_step(parent: &mut Effect, raw: &RawParams, fade_step: f64) {
+    let time = parent.smooth(Param::Time, raw.time);
+    let vibe = parent.smooth(Param::Vibe, raw.vibe);
+    let age  = parent.smooth(Param::Age, raw.age);
+    for &(id, val) in [
+        (Param::Feedback, raw.fb), (Param::Tone, raw.tone), (Param::Distortion, raw.sat),
+        (Param::Moisture, raw.wet), (Param::Freeze, raw.freeze), (Param::Noise, raw.noise),
+        (Param::Width, raw.width), (Param::Duck, raw.duck),
+    ].iter() {
+        parent.smooth(id, val);
+    }
+    track_frozen(parent, raw, time);
+    parent.micro(Param::Flutter, raw.flutter, age * age);
+    parent.micro(Param::Dropouts, raw.drop_amt, age);
+    parent.micro(Param::WowCenter, raw.wow_c, vibe * 8.0);
+    parent.micro(Param::WowSide, raw.wow_s, vibe * 10.0);
+
+    parent.lfo_1.step();
+    parent.lfo_2.step();
+    parent.lfo_3.step();
+    parent.lfo_4.step();
+    parent.lfo_5.step();
+    move_heads(parent, raw, time, false);
+    flutter_step(parent, raw.scrape);
+
+    parent.drop_l.next_gain();
+    parent.drop_r.next_gain();
+    if raw.engine != Engine::Classic {
+        parent.drop_c.next_gain();
+    }
+    parent.hiss_l.step();
+    parent.hiss_r.step();
+    parent.mod_noise_l.step();
+    parent.mod_noise_r.step();
+
+    parent.fade_in = (parent.fade_in + fade_step).min(1.0);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use vst::host::HostBuffer;
+    use crate::logger::Logger;
+    use crate::testing;
+
+    // xorshift, so that a failing case can be run again
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> f64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            (self.0 >> 11) as f64 / (1u64 << 53) as f64
+        }
+
+        // a normalized value, the ends of the range are picked often
+        fn param(&mut self) -> f32 {
+            match (self.next() * 8.0) as u32 {
+                0 => 0.0,
+                1 => 1.0,
+                _ => self.next() as f32,
+            }
+        }
+
+        fn sample(&mut self) -> f32 {
+            match (self.next() * 64.0) as u32 {
+                0 => f32::NAN,
+                1 => f32::INFINITY,
+                2 => f32::NEG_INFINITY,
+                3 => 1e30,
+                4 => -1e30,
+                5 => 1e-40,    // a denormal
+                6 => 0.0,
+                _ => ((self.next() * 2.0 - 1.0) * 4.0) as f32,
+            }
+        }
+    }
+
+    #[test]
+    fn survives_random_params_and_inputs() {
+        let sr = 44100.0;
+        let dir = std::env::temp_dir().join("vibe_machine_tests");
+        let log_name = format!("fuzz_{}", std::process::id());
+        let mut effect = testing::effect(sr);
+        effect.logger = Arc::new(Logger::new(dir.to_str().unwrap(), &log_name, true));
+
+        let mut rng = Rng(0x9e3779b97f4a7c15);
+        for round in 0..200 {
+            for spec in PARAMS.iter() {
+                testing::set(&effect, spec.id, rng.param());
+            }
+            let len = 1 + (rng.next() * 1024.0) as usize;
+            let inputs: Vec<Vec<f32>> = (0..4).map(|_| (0..len).map(|_| rng.sample()).collect()).collect();
+            let block = 1 + (rng.next() * 512.0) as usize;
+            if round % 20 == 0 {
+                // as if something upstream of the guard had blown up
+                effect.fb_l = f64::NAN;
+            }
+
+            let out = testing::render(&mut effect, &inputs, 2, block);
+            for (ch, o) in out.iter().enumerate() {
+                if let Some(pos) = o.iter().position(|x| !x.is_finite()) {
+                    panic!("round {}: channel {} sample {} is {}", round, ch, pos, o[pos]);
+                }
+            }
+        }
+
+        let path = dir.join(log_name + ".log");
+        let log = std::fs::read_to_string(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert!(log.contains("non-finite value in the loop"));
+    }
+
+    #[test]
+    fn wet_signal_fades_back_in_after_a_reset() {
+        let sr = 44100.0;
+        let mut effect = testing::effect(sr);
+        effect.logger = Arc::new(Logger::new("", "", false));
+        testing::set(&effect, Param::Time, 0.0);
+        testing::set(&effect, Param::Moisture, 1.0);
+        let noise = |len: usize| {
+            let mut rng = Rng(1);
+            vec![(0..len).map(|_| (rng.next() - 0.5) as f32).collect::<Vec<f32>>(); 2]
+        };
+        let ms = |t: f64| (t * sr / 1000.0) as usize;
+
+        testing::render(&mut effect, &noise(ms(500.0)), 2, 64);
+        // the NaN goes round the tape once before it reaches the guard
+        effect.fb_l = f64::NAN;
+        let mut waited = 0;
+        while effect.fade_in > 0.0 {
+            assert!(waited < ms(1000.0), "the NaN never reached the guard");
+            testing::render(&mut effect, &noise(64), 2, 64);
+            waited += 64;
+        }
+
+        // halfway through the fade
+        testing::render(&mut effect, &noise(ms(RECOVERY_FADE * 0.5)), 2, 64);
+        assert!((effect.fade_in - 0.5).abs() < 0.01, "fade is at {}", effect.fade_in);
+
+        let out = testing::render(&mut effect, &noise(ms(RECOVERY_FADE * 2.0)), 2, 64);
+        assert_eq!(effect.fade_in, 1.0);
+        let tail = &out[0][ms(RECOVERY_FADE)..];
+        let rms = (tail.iter().map(|x| (x * x) as f64).sum::<f64>() / tail.len() as f64).sqrt();
+        assert!(rms > 0.01, "wet signal is at {} rms", rms);
+    }
+
+    #[test]
+    fn mid_side_mirrors_left_and_right() {
+        let sr = 44100.0;
+        let mut rng = Rng(7);
+        let burst: Vec<f32> = (0..sr as usize).map(|i| if i < 4410 {(rng.next() - 0.5) as f32} else {0.0}).collect();
+        let silence = vec![0.0f32; burst.len()];
+
+        for engine in [0.0, 1.0].iter() {
+            let run = |inputs: &[Vec<f32>]| {
+                let mut effect = testing::effect(sr);
+                testing::set(&effect, Param::Engine, *engine);
+                testing::set(&effect, Param::StereoMode, 1.0);
+                testing::set(&effect, Param::Time, 0.02);
+                testing::set(&effect, Param::Vibe, 0.5);
+                testing::set(&effect, Param::Age, 0.5);
+                testing::set(&effect, Param::Feedback, 0.4);
+                testing::set(&effect, Param::Distortion, 0.5);
+                testing::set(&effect, Param::Pitch, 0.038);
+                testing::set(&effect, Param::Width, 0.75);
+                testing::render(&mut effect, inputs, 2, 512)
+            };
+            let left = run(&[burst.clone(), silence.clone()]);
+            let right = run(&[silence.clone(), burst.clone()]);
+
+            // the same echoes, on the other side
+            assert!(left[0].iter().map(|x| x.abs()).fold(0.0, f32::max) > 0.01);
+            for i in 0..burst.len() {
+                assert!((left[0][i] - right[1][i]).abs() < 1e-6, "engine {}: sample {}, {} against {}", engine, i, left[0][i], right[1][i]);
+                assert!((left[1][i] - right[0][i]).abs() < 1e-6, "engine {}: sample {}, {} against {}", engine, i, left[1][i], right[0][i]);
+            }
+        }
+    }
+
+    // an instance with everything that makes the sides differ turned up
+    fn wide_effect(sr: f64) -> Effect {
+        let effect = testing::effect(sr);
+        testing::set(&effect, Param::Engine, 1.0);
+        testing::set(&effect, Param::Time, 0.02);
+        testing::set(&effect, Param::Vibe, 0.5);
+        testing::set(&effect, Param::Age, 0.5);
+        testing::set(&effect, Param::Feedback, 0.4);
+        testing::set(&effect, Param::Pitch, 0.038);
+        testing::set(&effect, Param::Width, 1.0);
+        return effect;
+    }
+
+    fn noise_burst(len: usize) -> Vec<f32> {
+        let mut rng = Rng(3);
+        (0..len).map(|i| if i < len / 4 {(rng.next() - 0.5) as f32} else {0.0}).collect()
+    }
+
+    #[test]
+    fn mono_input_feeds_both_sides() {
+        let input = noise_burst(22050);
+        let mono = testing::render(&mut wide_effect(44100.0), std::slice::from_ref(&input), 2, 512);
+        let stereo = testing::render(&mut wide_effect(44100.0), &[input.clone(), input.clone()], 2, 512);
+        assert!(mono[0] != mono[1]);
+        assert_eq!(mono, stereo);
+    }
+
+    #[test]
+    fn mono_output_is_the_sum_of_both_sides() {
+        let input = noise_burst(22050);
+        let mono = testing::render(&mut wide_effect(44100.0), std::slice::from_ref(&input), 1, 512);
+        let stereo = testing::render(&mut wide_effect(44100.0), &[input.clone(), input.clone()], 2, 512);
+        for i in 0..input.len() {
+            let sum = (stereo[0][i] + stereo[1][i]) * 0.5;
+            assert!((mono[0][i] - sum).abs() < 1e-6, "sample {}: {} against {}", i, mono[0][i], sum);
+        }
+    }
+
+    #[test]
+    fn empty_blocks_change_nothing() {
+        let input = noise_burst(22050);
+        let inputs = [input.clone(), input.clone()];
+        let reference = testing::render(&mut wide_effect(44100.0), &inputs, 2, 512);
+
+        let mut effect = wide_effect(44100.0);
+        let mut host_buffer: HostBuffer<f32> = HostBuffer::new(2, 2);
+        let mut out = vec![Vec::new(); 2];
+        for chunk in 0..input.len() / 512 + 1 {
+            let end = ((chunk + 1) * 512).min(input.len());
+            let part: Vec<Vec<f32>> = inputs.iter().map(|i| i[chunk * 512..end].to_vec()).collect();
+            for (o, p) in out.iter_mut().zip(testing::render(&mut effect, &part, 2, 512)) {
+                o.extend(p);
+            }
+
+            // a zero-length block, like some hosts send around transport changes
+            let empty: [&[f32]; 2] = [&[], &[]];
+            let (mut out_l, mut out_r) = ([0.0f32; 0], [0.0f32; 0]);
+            let mut outs: [&mut [f32]; 2] = [&mut out_l, &mut out_r];
+            effect.process(&mut host_buffer.bind(&empty, &mut outs));
+        }
+        assert_eq!(out, reference);
+    }
+
+    #[test]
+    fn runs_without_outputs() {
+        let input = noise_burst(22050);
+        let half = input.len() / 2;
+        let reference = testing::render(&mut wide_effect(44100.0), &[input.clone(), input.clone()], 2, 512);
+
+        // the state moves on as usual while no output is connected
+        let mut effect = wide_effect(44100.0);
+        let first: Vec<f32> = input[..half].to_vec();
+        let second: Vec<f32> = input[half..].to_vec();
+        assert!(testing::render(&mut effect, &[first.clone(), first], 0, 512).is_empty());
+        let out = testing::render(&mut effect, &[second.clone(), second], 2, 512);
+        assert_eq!(out[0][..], reference[0][half..]);
+        assert_eq!(out[1][..], reference[1][half..]);
+    }
+
+    #[test]
+    fn frozen_loop_keeps_its_highs() {
+        let sr = 44100.0;
+        // a loop of 4167.45 samples, as far from a whole sample as it gets
+        let time = 0.01;
+        let ms = |t: f64| (t * sr / 1000.0) as usize;
+        let mut rng = Rng(5);
+        let burst: Vec<f32> = (0..ms(60.0)).map(|_| (rng.next() - 0.5) as f32).collect();
+
+        // energy of the first difference, which is mostly the highs
+        let highs = |x: &[f32]| x.windows(2).map(|w| ((w[1] - w[0]) as f64).powi(2)).sum::<f64>();
+
+        for engine in [0.0, 1.0].iter() {
+            let mut effect = testing::effect(sr);
+            testing::set(&effect, Param::Engine, *engine);
+            testing::set(&effect, Param::Time, time);
+            testing::set(&effect, Param::Moisture, 1.0);
+            // long enough for the time smoothing to settle
+            testing::render(&mut effect, &[vec![0.0; ms(10000.0)]], 2, 512);
+            testing::render(&mut effect, &[burst.clone()], 2, 512);
+            testing::set(&effect, Param::Freeze, 1.0);
+            let out = testing::render(&mut effect, &[vec![0.0; ms(6000.0)]], 2, 512);
+
+            // a second at a time, which covers several passes of every line,
+            // once the freeze has settled
+            let before = highs(&out[0][ms(1000.0)..ms(2000.0)]);
+            let after = highs(&out[0][ms(5000.0)..ms(6000.0)]);
+            assert!(before > 1.0);
+            let loss = 10.0 * (before / after).log10();
+            assert!(loss.abs() < 0.25, "engine {}: {:.2} dB lost in 4 s", engine, loss);
+        }
+    }
+}
@@ -0,0 +1,158 @@
+// stl stuff
+use std::convert::TryInto;
+use std::sync::atomic::Ordering;
+
+// internal dependencies
+use super::EffectParameters;
+use crate::params::{Param, PARAMS};
+
+// === GLOBALS ===
+// NOTE: the chunk layout is (all little-endian):
+// - 4 bytes of magic, "VIBE"
+// - u32 format version
+// - u32 parameter count
+// - one f32 per parameter, in index order
+// - hidden state, whose layout depends on the format version
+// New parameters can be appended without bumping the version, since the count
+// is stored in the chunk. Bump the version only when the hidden state changes.
+//
+// hidden state by version:
+// - 1: none
+// - 2: u64 instance seed
+const MAGIC: &[u8; 4] = b"VIBE";
+const VERSION: u32 = 2;
+const HEADER_LEN: usize = 12;
+
+
+/// Serialize all parameters (and hidden state) into a versioned chunk.
+pub fn serialize(params: &EffectParameters) -> Vec<u8> {
+    let count = params.values.len();
+    let mut data = Vec::with_capacity(HEADER_LEN + count * 4 + 8);
+
+    data.extend_from_slice(MAGIC);
+    data.extend_from_slice(&VERSION.to_le_bytes());
+    data.extend_from_slice(&(count as u32).to_le_bytes());
+    for p in params.values.iter() {
+        data.extend_from_slice(&p.get().to_le_bytes());
+    }
+    data.extend_from_slice(&params.instance_seed.load(Ordering::Relaxed).to_le_bytes());
+
+    return data;
+}
+
+/// Restore parameters (and hidden state) from a chunk made by `serialize`.
+/// Returns false and leaves the parameters untouched if the chunk is malformed
+/// or was written by a newer version of the plugin. Parameters missing from the
+/// chunk are reset to their defaults.
+pub fn deserialize(params: &EffectParameters, data: &[u8]) -> bool {
+    if data.len() < HEADER_LEN || &data[0..4] != MAGIC {
+        return false;
+    }
+    let version = read_u32(data, 4);
+    let count = read_u32(data, 8) as usize;
+    let hidden = HEADER_LEN + count * 4;
+    let hidden_len = if version >= 2 {8} else {0};
+    if version > VERSION || data.len() < hidden + hidden_len {
+        return false;
+    }
+
+    for i in 0..count {
+        let val = f32::from_le_bytes(data[HEADER_LEN + i * 4..HEADER_LEN + i * 4 + 4].try_into().unwrap());
+
+        // parameters that were removed since the chunk was saved are ignored
+        match params.values.get(i) {
+            Some(p) => p.set(val.max(0.0).min(1.0)),
+            None => (),
+        };
+    }
+
+    for i in count..params.values.len() {
+        params.values[i].set(PARAMS[i].default);
+    }
+
+    // sessions from before the seed existed get the seed control's default,
+    // "instance", on an instance seed of 0, which gives the fixed seeds they
+    // were made with, see `algo::derive_seed`
+    let seed = if version >= 2 {
+        u64::from_le_bytes(data[hidden..hidden + 8].try_into().unwrap())
+    } else {
+        0
+    };
+    params.instance_seed.store(seed, Ordering::Relaxed);
+
+    return true;
+}
+
+fn read_u32(data: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(data[at..at + 4].try_into().unwrap())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a chunk laid out by hand, as an older version would have written it
+    fn chunk(version: u32, values: &[f32], seed: Option<u64>) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(MAGIC);
+        data.extend_from_slice(&version.to_le_bytes());
+        data.extend_from_slice(&(values.len() as u32).to_le_bytes());
+        for v in values.iter() {
+            data.extend_from_slice(&v.to_le_bytes());
+        }
+        if let Some(seed) = seed {
+            data.extend_from_slice(&seed.to_le_bytes());
+        }
+        return data;
+    }
+
+    fn values(params: &EffectParameters) -> Vec<f32> {
+        params.values.iter().map(|v| v.get()).collect()
+    }
+
+    #[test]
+    fn round_trip() {
+        let saved = EffectParameters::default();
+        for (i, v) in saved.values.iter().enumerate() {
+            v.set((i + 1) as f32 / (PARAMS.len() + 2) as f32);
+        }
+        saved.instance_seed.store(0x0123_4567_89ab_cdef, Ordering::Relaxed);
+
+        let loaded = EffectParameters::default();
+        assert!(deserialize(&loaded, &serialize(&saved)));
+        assert_eq!(values(&loaded), values(&saved));
+        assert_eq!(loaded.instance_seed.load(Ordering::Relaxed), 0x0123_4567_89ab_cdef);
+    }
+
+    #[test]
+    fn version_1_loads_as_legacy() {
+        let macros = [0.1, 0.2, 0.3, 0.4, 0.5, 0.6, 0.7, 0.8];
+        let params = EffectParameters::default();
+        assert!(deserialize(&params, &chunk(1, &macros, None)));
+        assert_eq!(&values(&params)[..8], &macros[..]);
+        assert_eq!(params.get(Param::Engine), 0.0);
+        assert_eq!(params.instance_seed.load(Ordering::Relaxed), 0);
+        assert_eq!(params.seed(), 0);
+    }
+
+    #[test]
+    fn rejects_bad_chunks() {
+        let good = serialize(&EffectParameters::default());
+        let mut wrong_magic = good.clone();
+        wrong_magic[0] = b'X';
+        let newer = chunk(VERSION + 1, &[0.5; 8], Some(1));
+
+        for data in [&good[..good.len() - 1], &good[..HEADER_LEN - 1], &[][..], &wrong_magic[..], &newer[..]].iter() {
+            let params = EffectParameters::default();
+            for v in params.values.iter() {
+                v.set(0.25);
+            }
+            params.instance_seed.store(42, Ordering::Relaxed);
+
+            assert!(!deserialize(&params, data));
+            assert!(values(&params).iter().all(|v| *v == 0.25));
+            assert_eq!(params.instance_seed.load(Ordering::Relaxed), 42);
+        }
+    }
+}
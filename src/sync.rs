@@ -0,0 +1,67 @@
+// === TEMPO SYNC ===
+// NOTE: only the center delay time is synced, the side heads keep their
+// log2(e) and log2(e)^2 ratios to it, so they still never line up with the grid.
+
+// straight note divisions, in quarter notes, from 1/32 to 4 bars
+const DIVISIONS: [f64; 8] = [0.125, 0.25, 0.5, 1.0, 2.0, 4.0, 8.0, 16.0];
+const DOTTED:  f64 = 1.5;
+const TRIPLET: f64 = 2.0 / 3.0;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum SyncMode {
+    Off,
+    Straight,
+    Dotted,
+    Triplet,
+}
+
+impl SyncMode {
+    pub fn from_param(val: f32) -> Self {
+        match (val * 3.0).round() as u32 {
+            0 => SyncMode::Off,
+            1 => SyncMode::Straight,
+            2 => SyncMode::Dotted,
+            _ => SyncMode::Triplet,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SyncMode::Off      => "off",
+            SyncMode::Straight => "straight",
+            SyncMode::Dotted   => "dotted",
+            SyncMode::Triplet  => "triplet",
+        }
+    }
+}
+
+/// Snap a delay time in ms to the closest note division at the given tempo,
+/// only divisions between `min` and `max` ms are considered. If none fits (at
+/// extreme tempos) the time is returned unchanged.
+pub fn quantize(time: f64, tempo: f64, mode: SyncMode, min: f64, max: f64) -> f64 {
+    let factor = match mode {
+        SyncMode::Off      => return time,
+        SyncMode::Straight => 1.0,
+        SyncMode::Dotted   => DOTTED,
+        SyncMode::Triplet  => TRIPLET,
+    };
+    if tempo <= 0.0 {
+        return time;
+    }
+
+    let beat = 60000.0 / tempo;
+    let mut best = time;
+    let mut best_dist = f64::INFINITY;
+    for div in DIVISIONS.iter() {
+        let candidate = div * factor * beat;
+        // distance is measured in octaves, so that long and short times are
+        // treated the same way
+        let dist = (candidate / time).log2().abs();
+        if candidate >= min && candidate <= max && dist < best_dist {
+            best = candidate;
+            best_dist = dist;
+        }
+    }
+
+    return best;
+}
@@ -0,0 +1,62 @@
+// Helpers for the tests that run audio through the whole plugin, the way a
+// host would.
+
+// third-party libs
+use vst::host::HostBuffer;
+use vst::plugin::{Plugin, PluginParameters};
+
+// internal dependencies
+use super::Effect;
+use crate::params::Param;
+
+/// A plugin instance, set up and switched on like a host would, with a fixed
+/// seed so that every instance sounds the same.
+pub fn effect(sr: f64) -> Effect {
+    let mut effect = Effect::default();
+    set(&effect, Param::Seed, 0.5);
+    effect.init();
+    effect.set_sample_rate(sr as f32);
+    effect.resume();
+    return effect;
+}
+
+/// Set a parameter from its normalized value, like a host would.
+pub fn set(effect: &Effect, id: Param, val: f32) {
+    effect.params.set_parameter(id as i32, val);
+}
+
+/// Run the inputs through the plugin in blocks of `block` samples, returns
+/// `outputs` output channels.
+pub fn render(effect: &mut Effect, inputs: &[Vec<f32>], outputs: usize, block: usize) -> Vec<Vec<f32>> {
+    render_automated(effect, inputs, outputs, block, &[])
+}
+
+/// Same as `render`, with parameter changes at the given sample positions,
+/// sent through the change queue like `vibe_render` does. `changes` must be
+/// sorted by position.
+pub fn render_automated(effect: &mut Effect, inputs: &[Vec<f32>], outputs: usize, block: usize, changes: &[(usize, Param, f32)]) -> Vec<Vec<f32>> {
+    let len = inputs.iter().map(|i| i.len()).max().unwrap_or(0);
+    let mut out = vec![vec![0.0f32; len]; outputs];
+    let mut host_buffer: HostBuffer<f32> = HostBuffer::new(inputs.len(), outputs);
+    let mut next_change = 0;
+    let mut pos = 0;
+    while pos < len {
+        let end = (pos + block).min(len);
+        while next_change < changes.len() && changes[next_change].0 < end {
+            let (frame, id, val) = changes[next_change];
+            assert!(effect.queue_parameter_change(frame.saturating_sub(pos), id as i32, val));
+            next_change += 1;
+        }
+        let ins: Vec<&[f32]> = inputs.iter().map(|i| &i[pos..end]).collect();
+        let mut outs: Vec<&mut [f32]> = out.iter_mut().map(|o| &mut o[pos..end]).collect();
+        let mut buffer = host_buffer.bind(&ins, &mut outs);
+        effect.process(&mut buffer);
+        pos = end;
+    }
+    return out;
+}
+
+/// Index of the first sample louder than `level`.
+pub fn onset(signal: &[f32], level: f32) -> Option<usize> {
+    signal.iter().position(|x| x.abs() > level)
+}
@@ -76,7 +76,7 @@ impl Widget for CustomKnob {
     type Ret = Entity;
     fn on_build(&mut self, state: &mut State, entity: Entity) -> Self::Ret {
         
-        let value = self.params.dict.get(&self.dict_key).unwrap().get();
+        let value = self.params.values[self.dict_key as usize].get();
         self.control.on_build(state, entity);
         self.last_value    = value;
         self.control.value = value;
@@ -123,7 +123,7 @@ impl Widget for CustomKnob {
             match slider_event {
                 SliderEvent::ValueChanged(val) => {
                     self.last_value = *val;
-                    self.params.dict.get(&self.dict_key).unwrap().set(*val);
+                    self.params.values[self.dict_key as usize].set(*val);
                 }
 
                 _ => {}